@@ -0,0 +1,25 @@
+extern crate rust_chunk;
+
+use rust_chunk::config::ServerConfig;
+use rust_chunk::server::{console, Server};
+
+fn main() {
+    let config = match ServerConfig::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    console::help();
+    let console = match console::spawn(config.control_addr) {
+        Ok(console) => console,
+        Err(e) => {
+            eprintln!("couldn't open the control socket: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let mut server = Server::bind(&config, console).expect("couldn't bind to address");
+    server.run();
+}
@@ -0,0 +1,590 @@
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::net::{SocketAddr, ToSocketAddrs};
+
+pub const DEFAULT_BIND_ADDR: &'static str = "127.0.0.1:45001";
+pub const DEFAULT_SERVER_ADDR: &'static str = "127.0.0.1:45000";
+pub const DEFAULT_CONFIG_FILE: &'static str = "chunk.conf";
+// Two server ticks at the default rate, so one lost snapshot goes unnoticed.
+pub const DEFAULT_INTERPOLATION_DELAY_MS: u32 = 100;
+pub const DEFAULT_SIM_RATE: u32 = 60;
+
+pub const DEFAULT_SERVER_BIND_ADDR: &'static str = "127.0.0.1:45000";
+pub const DEFAULT_TICK_RATE: u32 = 20;
+pub const DEFAULT_CONTROL_ADDR: &'static str = "127.0.0.1:45100";
+pub const DEFAULT_SERVER_CONFIG_FILE: &'static str = "chunk-server.conf";
+
+// Every setting can come from the config file, the environment or the
+// command line. Later sources win: file < env < flags.
+
+// (flag, setting key, value for switches that take no argument)
+type Flag = (&'static str, &'static str, Option<&'static str>);
+
+const BINDING_PREFIX: &'static str = "bind.";
+
+const CLIENT_ENV_CONFIG: &'static str = "CHUNK_CONFIG";
+const CLIENT_ENV: &'static [(&'static str, &'static str)] = &[
+    ("CHUNK_BIND", "bind"),
+    ("CHUNK_SERVER", "server"),
+    ("CHUNK_EPHEMERAL_FALLBACK", "ephemeral_fallback"),
+    ("CHUNK_INTERPOLATION_DELAY", "interpolation_delay"),
+    ("CHUNK_SIM_RATE", "sim_rate"),
+    ("CHUNK_VSYNC", "vsync"),
+    ("CHUNK_MAX_FPS", "max_fps"),
+    ("CHUNK_SCREENSHOT", "screenshot"),
+    ("CHUNK_SCREENSHOT_SCENE", "screenshot_scene"),
+];
+const CLIENT_FLAGS: &'static [Flag] = &[
+    ("--bind", "bind", None),
+    ("--server", "server", None),
+    ("--ephemeral-fallback", "ephemeral_fallback", Some("true")),
+    (
+        "--no-ephemeral-fallback",
+        "ephemeral_fallback",
+        Some("false"),
+    ),
+    ("--interpolation-delay", "interpolation_delay", None),
+    ("--sim-rate", "sim_rate", None),
+    ("--vsync", "vsync", Some("true")),
+    ("--no-vsync", "vsync", Some("false")),
+    ("--max-fps", "max_fps", None),
+    ("--screenshot", "screenshot", None),
+    ("--screenshot-scene", "screenshot_scene", None),
+];
+
+const SERVER_ENV_CONFIG: &'static str = "CHUNK_SERVER_CONFIG";
+const SERVER_ENV: &'static [(&'static str, &'static str)] = &[
+    ("CHUNK_SERVER_BIND", "bind"),
+    ("CHUNK_TICK_RATE", "tick_rate"),
+    ("CHUNK_SERVER_CONTROL", "control"),
+];
+const SERVER_FLAGS: &'static [Flag] = &[
+    ("--bind", "bind", None),
+    ("--tick-rate", "tick_rate", None),
+    ("--control", "control", None),
+    ("--no-control", "control", Some("off")),
+];
+
+// Where one program looks for its settings, besides the command line.
+struct Sources<'a> {
+    env_config: &'static str,
+    default_file: &'a str,
+    env_keys: &'static [(&'static str, &'static str)],
+    flags: &'static [Flag],
+}
+
+const CLIENT_SOURCES: Sources<'static> = Sources {
+    env_config: CLIENT_ENV_CONFIG,
+    default_file: DEFAULT_CONFIG_FILE,
+    env_keys: CLIENT_ENV,
+    flags: CLIENT_FLAGS,
+};
+
+const SERVER_SOURCES: Sources<'static> = Sources {
+    env_config: SERVER_ENV_CONFIG,
+    default_file: DEFAULT_SERVER_CONFIG_FILE,
+    env_keys: SERVER_ENV,
+    flags: SERVER_FLAGS,
+};
+
+trait Settings {
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String>;
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub bind_addr: SocketAddr,
+    pub ephemeral_fallback: bool,
+    pub server_addr: SocketAddr,
+    // How far behind the newest snapshot remote players are drawn.
+    pub interpolation_delay_ms: u32,
+    // Fixed simulation steps per second, independent of the frame rate.
+    pub sim_rate: u32,
+    pub vsync: bool,
+    // Frames per second to stop at; 0 leaves the frame rate to vsync.
+    pub max_fps: u32,
+    // `bind.<action> = <inputs>` lines from the config file, in order. The
+    // client checks them against its own action and input names.
+    pub bindings: Vec<(String, String)>,
+    // When set, the client draws one frame of `screenshot_scene` without
+    // showing a window, saves it here as PNG and exits.
+    pub screenshot: Option<String>,
+    pub screenshot_scene: Scene,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scene {
+    Lobby,
+    Room,
+}
+
+impl Config {
+    pub fn default() -> Config {
+        Config {
+            bind_addr: DEFAULT_BIND_ADDR.parse().unwrap(),
+            ephemeral_fallback: true,
+            server_addr: DEFAULT_SERVER_ADDR.parse().unwrap(),
+            interpolation_delay_ms: DEFAULT_INTERPOLATION_DELAY_MS,
+            sim_rate: DEFAULT_SIM_RATE,
+            vsync: true,
+            max_fps: 0,
+            bindings: Vec::new(),
+            screenshot: None,
+            screenshot_scene: Scene::Lobby,
+        }
+    }
+
+    pub fn load() -> Result<Config, String> {
+        let args: Vec<String> = env::args().skip(1).collect();
+        Config::from_args(&args)
+    }
+
+    pub fn from_args(args: &[String]) -> Result<Config, String> {
+        let mut config = Config::default();
+        let usage = format!(
+            "Usage: rust-chunk [OPTIONS]\n\
+             Options: --config FILE, --bind ADDR, --server ADDR, --[no-]ephemeral-fallback,\n\
+             \x20        --interpolation-delay MS, --sim-rate HZ, --[no-]vsync, --max-fps FPS,\n\
+             \x20        --screenshot FILE, --screenshot-scene lobby|room\n\
+             Environment: {}, {}\n\
+             Defaults: bind {}, server {}, interpolation delay {} ms, sim rate {} Hz, vsync on, \
+             no frame cap, config file {}",
+            CLIENT_ENV_CONFIG,
+            env_names(CLIENT_ENV),
+            DEFAULT_BIND_ADDR,
+            DEFAULT_SERVER_ADDR,
+            DEFAULT_INTERPOLATION_DELAY_MS,
+            DEFAULT_SIM_RATE,
+            DEFAULT_CONFIG_FILE
+        );
+        load(
+            &mut config,
+            args,
+            &|name| env::var(name).ok(),
+            &CLIENT_SOURCES,
+            &usage,
+        )?;
+        Ok(config)
+    }
+}
+
+impl Settings for Config {
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "bind" => self.bind_addr = parse_addr(value)?,
+            "server" => self.server_addr = parse_addr(value)?,
+            "ephemeral_fallback" => self.ephemeral_fallback = parse_bool(value)?,
+            "interpolation_delay" => self.interpolation_delay_ms = parse_millis(value)?,
+            "sim_rate" => self.sim_rate = parse_tick_rate(value)?,
+            "vsync" => self.vsync = parse_bool(value)?,
+            "max_fps" => self.max_fps = parse_max_fps(value)?,
+            "screenshot" => self.screenshot = Some(value.to_string()),
+            "screenshot_scene" => self.screenshot_scene = parse_scene(value)?,
+            _ if key.starts_with(BINDING_PREFIX) => {
+                let action = &key[BINDING_PREFIX.len()..];
+                self.bindings.push((action.to_string(), value.to_string()));
+            }
+            _ => return Err(format!("unknown setting {:?}", key)),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub bind_addr: SocketAddr,
+    pub tick_rate: u32,
+    // Local TCP address accepting console commands from scripts.
+    pub control_addr: Option<SocketAddr>,
+}
+
+impl ServerConfig {
+    pub fn default() -> ServerConfig {
+        ServerConfig {
+            bind_addr: DEFAULT_SERVER_BIND_ADDR.parse().unwrap(),
+            tick_rate: DEFAULT_TICK_RATE,
+            control_addr: Some(DEFAULT_CONTROL_ADDR.parse().unwrap()),
+        }
+    }
+
+    pub fn load() -> Result<ServerConfig, String> {
+        let args: Vec<String> = env::args().skip(1).collect();
+        ServerConfig::from_args(&args)
+    }
+
+    pub fn from_args(args: &[String]) -> Result<ServerConfig, String> {
+        let mut config = ServerConfig::default();
+        let usage = format!(
+            "Usage: server [--config FILE] [--bind ADDR] [--tick-rate HZ] [--control ADDR | --no-control]\n\
+             Environment: {}, {}\n\
+             Defaults: bind {}, tick rate {}, control {}, config file {}",
+            SERVER_ENV_CONFIG,
+            env_names(SERVER_ENV),
+            DEFAULT_SERVER_BIND_ADDR,
+            DEFAULT_TICK_RATE,
+            DEFAULT_CONTROL_ADDR,
+            DEFAULT_SERVER_CONFIG_FILE
+        );
+        load(
+            &mut config,
+            args,
+            &|name| env::var(name).ok(),
+            &SERVER_SOURCES,
+            &usage,
+        )?;
+        Ok(config)
+    }
+}
+
+impl Settings for ServerConfig {
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "bind" => self.bind_addr = parse_addr(value)?,
+            "tick_rate" => self.tick_rate = parse_tick_rate(value)?,
+            "control" => {
+                self.control_addr = match value {
+                    "off" | "none" => None,
+                    _ => Some(parse_control_addr(value)?),
+                }
+            }
+            _ => return Err(format!("unknown setting {:?}", key)),
+        }
+        Ok(())
+    }
+}
+
+fn load<S: Settings, E: Fn(&str) -> Option<String>>(
+    settings: &mut S,
+    args: &[String],
+    env: &E,
+    sources: &Sources,
+    usage: &str,
+) -> Result<(), String> {
+    let path = match flag_value(args, "--config")? {
+        Some(path) => Some(path),
+        None => env(sources.env_config),
+    };
+    match path {
+        Some(path) => apply_file(settings, &path, true)?,
+        None => apply_file(settings, sources.default_file, false)?,
+    }
+
+    for &(name, key) in sources.env_keys.iter() {
+        if let Some(value) = env(name) {
+            settings
+                .set(key, &value)
+                .map_err(|e| format!("{}: {}", name, e))?;
+        }
+    }
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--config" {
+            next_value(&mut iter, arg)?;
+            continue;
+        }
+        if arg == "--help" || arg == "-h" {
+            return Err(usage.to_string());
+        }
+        match sources.flags.iter().find(|&&(flag, _, _)| flag == arg) {
+            Some(&(_, key, Some(value))) => settings.set(key, value)?,
+            Some(&(_, key, None)) => settings.set(key, next_value(&mut iter, arg)?)?,
+            None => return Err(format!("unknown argument {:?}\n{}", arg, usage)),
+        }
+    }
+
+    Ok(())
+}
+
+fn apply_file<S: Settings>(settings: &mut S, path: &str, required: bool) -> Result<(), String> {
+    let mut source = String::new();
+    match File::open(path) {
+        Ok(mut file) => {
+            file.read_to_string(&mut source)
+                .map_err(|e| format!("{}: {}", path, e))?;
+        }
+        Err(ref e) if !required && e.kind() == ::std::io::ErrorKind::NotFound => {
+            return Ok(());
+        }
+        Err(e) => return Err(format!("{}: {}", path, e)),
+    }
+
+    for (n, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut pair = line.splitn(2, '=');
+        let key = pair.next().unwrap().trim();
+        let value = match pair.next() {
+            Some(value) => value.trim(),
+            None => return Err(format!("{}:{}: expected `key = value`", path, n + 1)),
+        };
+        settings
+            .set(key, value)
+            .map_err(|e| format!("{}:{}: {}", path, n + 1, e))?;
+    }
+
+    Ok(())
+}
+
+fn flag_value(args: &[String], flag: &str) -> Result<Option<String>, String> {
+    match args.iter().position(|arg| arg == flag) {
+        Some(i) => match args.get(i + 1) {
+            Some(value) => Ok(Some(value.clone())),
+            None => Err(format!("{} expects a value", flag)),
+        },
+        None => Ok(None),
+    }
+}
+
+fn next_value<'a, I>(iter: &mut I, flag: &str) -> Result<&'a str, String>
+where
+    I: Iterator<Item = &'a String>,
+{
+    match iter.next() {
+        Some(value) => Ok(value.as_str()),
+        None => Err(format!("{} expects a value", flag)),
+    }
+}
+
+fn env_names(env_keys: &[(&str, &str)]) -> String {
+    env_keys
+        .iter()
+        .map(|&(name, _)| name)
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
+
+fn parse_addr(value: &str) -> Result<SocketAddr, String> {
+    let mut addrs = value
+        .to_socket_addrs()
+        .map_err(|e| format!("bad address {:?}: {}", value, e))?;
+    match addrs.next() {
+        Some(addr) => Ok(addr),
+        None => Err(format!("address {:?} did not resolve", value)),
+    }
+}
+
+// The control socket takes commands with no authentication, so it only ever
+// listens on the local machine.
+fn parse_control_addr(value: &str) -> Result<SocketAddr, String> {
+    let addr = parse_addr(value)?;
+    if !addr.ip().is_loopback() {
+        return Err(format!(
+            "control address {:?} must be a loopback address",
+            value
+        ));
+    }
+    Ok(addr)
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => Err(format!("expected a boolean, got {:?}", value)),
+    }
+}
+
+fn parse_millis(value: &str) -> Result<u32, String> {
+    match value.parse::<u32>() {
+        Ok(ms) if ms <= 1000 => Ok(ms),
+        _ => Err(format!(
+            "expected milliseconds between 0 and 1000, got {:?}",
+            value
+        )),
+    }
+}
+
+fn parse_max_fps(value: &str) -> Result<u32, String> {
+    match value.parse::<u32>() {
+        Ok(fps) if fps <= 1000 => Ok(fps),
+        _ => Err(format!(
+            "expected a frame rate between 0 (no cap) and 1000, got {:?}",
+            value
+        )),
+    }
+}
+
+fn parse_scene(value: &str) -> Result<Scene, String> {
+    match value {
+        "lobby" => Ok(Scene::Lobby),
+        "room" => Ok(Scene::Room),
+        _ => Err(format!("expected lobby or room, got {:?}", value)),
+    }
+}
+
+pub fn parse_tick_rate(value: &str) -> Result<u32, String> {
+    match value.parse::<u32>() {
+        Ok(rate) if rate > 0 && rate <= 1000 => Ok(rate),
+        _ => Err(format!(
+            "expected a tick rate between 1 and 1000, got {:?}",
+            value
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::process;
+
+    const NO_FILE: &'static str = "/nonexistent/chunk-test.conf";
+
+    // A config file private to one test.
+    fn file(name: &str, contents: &str) -> String {
+        let path: PathBuf = env::temp_dir().join(format!("chunk-{}-{}.conf", process::id(), name));
+        fs::File::create(&path)
+            .and_then(|mut file| file.write_all(contents.as_bytes()))
+            .unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn server(
+        args: &[&str],
+        vars: &[(&str, &str)],
+        default_file: &str,
+    ) -> Result<ServerConfig, String> {
+        let mut config = ServerConfig::default();
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let lookup = |name: &str| {
+            vars.iter()
+                .find(|&&(var, _)| var == name)
+                .map(|&(_, value)| value.to_string())
+        };
+        load(
+            &mut config,
+            &args,
+            &lookup,
+            &Sources {
+                default_file: default_file,
+                ..SERVER_SOURCES
+            },
+            "usage",
+        )?;
+        Ok(config)
+    }
+
+    #[test]
+    fn later_sources_win() {
+        let path = file(
+            "precedence",
+            "tick_rate = 10\nbind = 127.0.0.1:1000\ncontrol = off\n",
+        );
+        let env = [
+            ("CHUNK_TICK_RATE", "30"),
+            ("CHUNK_SERVER_BIND", "127.0.0.1:2000"),
+        ];
+
+        let config = server(&[], &[], &path).unwrap();
+        assert_eq!(config.tick_rate, 10);
+        assert_eq!(config.bind_addr, "127.0.0.1:1000".parse().unwrap());
+
+        let config = server(&[], &env, &path).unwrap();
+        assert_eq!(config.tick_rate, 30);
+        assert_eq!(config.bind_addr, "127.0.0.1:2000".parse().unwrap());
+
+        let config = server(&["--tick-rate", "40"], &env, &path).unwrap();
+        assert_eq!(config.tick_rate, 40);
+        assert_eq!(config.bind_addr, "127.0.0.1:2000".parse().unwrap());
+        // Nothing later touched it, so the file's setting stands.
+        assert_eq!(config.control_addr, None);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn config_file_can_be_named_by_flag_or_env() {
+        let from_env = file("from-env", "tick_rate = 5");
+        let from_flag = file("from-flag", "tick_rate = 6");
+        let env = [(SERVER_ENV_CONFIG, from_env.as_str())];
+
+        assert_eq!(server(&[], &env, NO_FILE).unwrap().tick_rate, 5);
+        let args = ["--config", from_flag.as_str()];
+        assert_eq!(server(&args, &env, NO_FILE).unwrap().tick_rate, 6);
+
+        fs::remove_file(from_env).unwrap();
+        fs::remove_file(from_flag).unwrap();
+    }
+
+    #[test]
+    fn only_the_default_file_may_be_missing() {
+        let config = server(&[], &[], NO_FILE).unwrap();
+        assert_eq!(config.tick_rate, DEFAULT_TICK_RATE);
+
+        assert!(server(&["--config", NO_FILE], &[], NO_FILE).is_err());
+        assert!(server(&[], &[(SERVER_ENV_CONFIG, NO_FILE)], NO_FILE).is_err());
+    }
+
+    #[test]
+    fn errors_say_where_the_bad_value_came_from() {
+        let path = file("errors", "# comment\n\ntick_rate = 0\n");
+        assert_eq!(
+            server(&[], &[], &path).unwrap_err(),
+            format!(
+                "{}:3: expected a tick rate between 1 and 1000, got \"0\"",
+                path
+            )
+        );
+        fs::remove_file(path).unwrap();
+
+        let env = [("CHUNK_TICK_RATE", "fast")];
+        assert!(server(&[], &env, NO_FILE)
+            .unwrap_err()
+            .starts_with("CHUNK_TICK_RATE: "));
+        assert!(server(&["--tick-rate"], &[], NO_FILE).is_err());
+        assert!(server(&["--frobnicate"], &[], NO_FILE).is_err());
+    }
+
+    #[test]
+    fn control_socket_stays_local() {
+        let args = ["--control", "127.0.0.1:45101"];
+        let config = server(&args, &[], NO_FILE).unwrap();
+        assert_eq!(
+            config.control_addr,
+            Some("127.0.0.1:45101".parse().unwrap())
+        );
+
+        assert!(server(&["--control", "0.0.0.0:45100"], &[], NO_FILE).is_err());
+        let env = [("CHUNK_SERVER_CONTROL", "192.168.1.2:45100")];
+        assert!(server(&[], &env, NO_FILE).is_err());
+    }
+
+    #[test]
+    fn client_switches_and_bindings() {
+        let mut config = Config::default();
+        let path = file("client", "vsync = off\nbind.jump = Space\nbind.jump = Up\n");
+        let args: Vec<String> = vec![
+            "--vsync".to_string(),
+            "--screenshot-scene".to_string(),
+            "room".to_string(),
+        ];
+        load(
+            &mut config,
+            &args,
+            &|_: &str| None,
+            &Sources {
+                default_file: &path,
+                ..CLIENT_SOURCES
+            },
+            "usage",
+        )
+        .unwrap();
+
+        assert!(config.vsync);
+        assert_eq!(config.screenshot_scene, Scene::Room);
+        assert_eq!(
+            config.bindings,
+            vec![
+                ("jump".to_string(), "Space".to_string()),
+                ("jump".to_string(), "Up".to_string()),
+            ]
+        );
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -3,125 +3,96 @@ use context::room_scene_context::*;
 use objects::*;
 use shaders;
 use shaders::*;
-use graphics;
 use cgmath::*;
-use gfx_gl::*;
-use rooms_ui::*;
-use timers;
-use protocol;
-use protocol::enums::MessageType;
+use input_state::Bindings;
+use rust_chunk::config::Config;
+use rust_chunk::network::{ConnectionState, Message, RefNetworkClient};
+use rust_chunk::render::{Batch, RefRenderer};
+use rust_chunk::rooms_ui::*;
+use rust_chunk::timers;
 use sdl2::event::Event;
-use std::ffi::CString;
-use std::net::UdpSocket;
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::time::Duration;
 
 pub struct MainSceneContext {
     program: Box<Shader>,
+    batch: RefCell<Batch>,
     background_program: Box<Shader>,
     background: Box<Rectangle>,
     matrix: Matrix4<f32>,
-    gl: Box<Gl>,
+    renderer: RefRenderer,
     rooms: Box<RoomUICollection>,
     switch_context: Option<RefSceneContext>,
-    timer: Box<timers::Timer>,
-    network: Rc<UdpSocket>,
+    clock: timers::RefTimer,
+    network: RefNetworkClient,
+    config: Config,
+    bindings: Bindings,
 }
 
 impl MainSceneContext {
-    pub fn new(gl: &Gl, network: &Rc<UdpSocket>) -> MainSceneContext {
-        let mut program = shaders::new(&gl);
-
-        let vsource = CString::new(smpl::DEFAULT_VERTEX).unwrap();
-        let vsb = vsource.to_bytes();
-
-        let fsource = CString::new(smpl::YELLOW_FRAGMENT).unwrap();
-        let fsb = fsource.to_bytes();
-
-        program.vertex_shader(vsb).fragment_shader(fsb).link();
-
-        let mut background_program = shaders::new(&gl);
-
-        let fsource = CString::new(smpl::BACKGROUND_FRAGMENT).unwrap();
-        let fsb = fsource.to_bytes();
+    pub fn new(
+        renderer: &RefRenderer,
+        network: &RefNetworkClient,
+        clock: &timers::RefTimer,
+        config: &Config,
+        bindings: &Bindings,
+    ) -> MainSceneContext {
+        let mut program = shaders::new(renderer);
+        program
+            .vertex_shader(smpl::BATCH_VERTEX)
+            .fragment_shader(smpl::BATCH_FRAGMENT)
+            .link();
 
+        let mut background_program = shaders::new(renderer);
         background_program
-            .vertex_shader(vsb)
-            .fragment_shader(fsb)
+            .vertex_shader(smpl::DEFAULT_VERTEX)
+            .fragment_shader(smpl::BACKGROUND_FRAGMENT)
             .link();
 
-        let mut rooms = RoomUICollection::new(1);
-        for mut room in rooms.each_mut() {
-            let gfx = graphics::Gfx::build_rectangle_sample(&gl, &room.calc_vertices());
-            room.gfx = Some(gfx);
+        // Room events sent while another scene was active went unheard, so
+        // start from a fresh catalogue.
+        if let Err(e) = network.borrow_mut().send_reliable(Message::ListRooms) {
+            println!("Network error: {:?}", e);
         }
 
         MainSceneContext {
             program: program,
+            batch: RefCell::new(Batch::new(renderer)),
             background_program: background_program,
-            background: Box::new(Rectangle::new(gl, 300f32, 200f32, 600f32, 400f32)),
+            background: Box::new(Rectangle::new(renderer, 300f32, 200f32, 600f32, 400f32)),
             matrix: ortho(0.0, 600.0, 0.0, 400.0, -1.0, 1.0),
-            gl: Box::new(gl.clone()),
-            rooms: rooms,
+            renderer: renderer.clone(),
+            rooms: RoomUICollection::new(),
             switch_context: None,
-            timer: timers::new(),
+            clock: clock.clone(),
             network: network.clone(),
-        }
-    }
-}
-
-impl SceneContext for MainSceneContext {
-    fn render(&self) {
-        unsafe {
-            self.gl.ClearColor(0.05, 0.05, 0.1, 1.0);
-            self.gl.Clear(COLOR_BUFFER_BIT);
-
-            self.background_program.use_program();
-            self.background_program
-                .uniform_matrix4fv("supermatrix", &self.matrix);
-            self.background_program
-                .uniform1f("time", self.timer.elapsed() as f32 / 10000f32);
-            self.background.draw();
-
-            self.program.use_program();
-            self.program.uniform_matrix4fv("supermatrix", &self.matrix);
-
-            for room in self.rooms.each() {
-                if room.is_active() {
-                    room.draw();
-                }
-            }
-        }
-    }
-
-    fn update(&mut self) {
-        let mut buf: Vec<u8> = vec![0; 128];
-        let recr = self.network.recv_from(&mut buf);
-
-        if recr.is_ok() {
-            match protocol::unpack(&buf) {
-                MessageType::ServerOn => for mut room in self.rooms.each_mut() {
-                    room.activate();
-                },
-
-                _ => (),
-            }
+            config: config.clone(),
+            bindings: bindings.clone(),
         }
     }
 
-    fn user_input(&mut self, event: Event) {
+    fn handle_event(&mut self, event: Event) {
         match event {
             Event::MouseButtonUp { x, y, .. } => {
-                match self.rooms.find_by_coords(x as u32, 400 - y as u32) {
+                match self.rooms.find_by_coords(x, 400 - y) {
                     Some(room) => {
-                        println!("Room {:?}", room.number());
-                        let msg = MessageType::MemberIn;
-                        let buf = protocol::pack(&msg);
-                        self.network.send_to(&buf, "127.0.0.1:45000").unwrap();
+                        let info = room.info().clone();
+                        println!(
+                            "Room {} {:?} ({}/{})",
+                            info.id, info.name, info.members, info.capacity
+                        );
+                        if let Err(e) = self.network.borrow_mut().send_reliable(Message::JoinRoom(info.id)) {
+                            println!("Network error: {:?}", e);
+                        }
 
                         self.switch_context = Some(Rc::new(RefCell::new(RoomSceneContext::new(
-                            &self.gl,
+                            &self.renderer,
                             &self.network,
+                            &self.clock,
+                            &self.config,
+                            &self.bindings,
+                            info,
                         ))));
                     }
 
@@ -129,10 +100,71 @@ impl SceneContext for MainSceneContext {
                 }
             }
 
+            Event::MouseWheel { y, .. } => self.rooms.scroll_by(y),
+
+            _ => (),
+        }
+    }
+}
+
+impl SceneContext for MainSceneContext {
+    fn render(&self, _alpha: f32) {
+        self.renderer.borrow_mut().clear(0.05, 0.05, 0.1, 1.0);
+
+        self.background_program.use_program();
+        self.background_program
+            .uniform1f("time", timers::as_millis_f32(self.clock.borrow().elapsed()) / 10000f32);
+        self.background.draw(&self.background_program, &self.matrix);
+
+        let scroll = Matrix4::from_translation(Vector3 {
+            x: 0f32,
+            y: self.rooms.scroll() as f32,
+            z: 0f32,
+        });
+        let view = self.matrix * scroll;
+
+        let mut batch = self.batch.borrow_mut();
+        self.rooms.draw(&mut batch, self.program.program());
+        batch.flush(&view);
+    }
+
+    fn update(&mut self, _dt: Duration) {}
+
+    fn user_input(&mut self, events: Vec<Event>) {
+        let mut events = events.into_iter();
+        while let Some(event) = events.next() {
+            self.handle_event(event);
+            // Once a room is picked the rest of the batch belongs to it.
+            if let Some(ref context) = self.switch_context {
+                let rest: Vec<Event> = events.collect();
+                if !rest.is_empty() {
+                    context.borrow_mut().user_input(rest);
+                }
+                break;
+            }
+        }
+    }
+
+    fn network_input(&mut self, msg: Message) {
+        match msg {
+            Message::RoomList(infos) => self.rooms.set_rooms(&infos),
+            Message::RoomAdded(info) => self.rooms.add_room(info),
+            Message::RoomRemoved(id) => self.rooms.remove_room(id),
+            Message::RoomUpdated(info) => self.rooms.update_room(info),
+
             _ => (),
         }
     }
 
+    fn connection_changed(&mut self, state: ConnectionState) {
+        match state {
+            ConnectionState::Connected => (),
+            _ => for room in self.rooms.each_mut() {
+                room.deactivate();
+            },
+        }
+    }
+
     fn switch_context(&self) -> Option<RefSceneContext> {
         match self.switch_context {
             Some(ref context) => Some(context.clone()),
@@ -1,46 +1,77 @@
 use context::scene_context::*;
+use context::main_scene_context::*;
 use objects::*;
+use objects::Transform;
 use shaders;
 use shaders::*;
-use timers;
 use input_state::*;
-use protocol;
-use protocol::enums::MessageType;
+use rust_chunk::config::Config;
+use rust_chunk::movement::{self, MAX_INPUT_MS};
+use rust_chunk::network::{ConnectionState, Input, Message, RefNetworkClient, RoomInfo};
+use rust_chunk::render::batch::Colour;
+use rust_chunk::render::{Batch, ProgramId, RefRenderer};
+use rust_chunk::timers;
 
 use cgmath::*;
-use gfx_gl::*;
 use sdl2::event::Event;
-use sdl2::mouse::MouseButton;
 
-use std::ffi::CString;
-use std::net::UdpSocket;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
 use std::rc::Rc;
+use std::time::Duration;
+
+// Inputs kept for replay while the server has not acknowledged them. More
+// than this means the server stopped answering, so the oldest are dropped.
+const MAX_PENDING_INPUTS: usize = 1024;
+
+const LOCAL_COLOUR: Colour = [1.0, 1.0, 0.4, 1.0];
+const GRID_COLOUR: Colour = [0.15, 0.15, 0.25, 1.0];
+const GRID_SPACING: f32 = 50f32;
 
 pub struct RoomSceneContext {
+    room: RoomInfo,
+    // Our member id, once the server confirmed the join.
+    member: Option<u16>,
+    members: Vec<u16>,
     circle: Circle,
+    // Where the local player was the update before, to render between.
+    previous: Transform,
+    // Movement shorter than the millisecond resolution of `Input`, carried
+    // into the next step.
+    input_carry_ms: f32,
+    // Local movement the server has yet to acknowledge, oldest first.
+    pending_inputs: VecDeque<Input>,
+    next_input: u16,
+    last_input_ack: Option<u16>,
+    // Everyone else in the room, by member id.
+    avatars: HashMap<u16, Avatar>,
     program: Box<Shader>,
+    batch: RefCell<Batch>,
     matrix: Matrix4<f32>,
-    gl: Box<Gl>,
-    timer: Box<timers::Timer>,
+    renderer: RefRenderer,
+    clock: timers::RefTimer,
     input_state: InputState,
-    network: Rc<UdpSocket>,
-    debug_move_start: i64,
-    debug_move_stop: i64,
+    network: RefNetworkClient,
+    config: Config,
+    switch_context: Option<RefSceneContext>,
 }
 
 impl RoomSceneContext {
-    pub fn new(gl: &Gl, network: &Rc<UdpSocket>) -> RoomSceneContext {
-        let circle = Circle::new(gl, 0f32, 0f32, 10f32);
-
-        let mut program = shaders::new(&gl);
-
-        let vsource = CString::new(smpl::DEFAULT_VERTEX).unwrap();
-        let vsb = vsource.to_bytes();
-
-        let fsource = CString::new(smpl::YELLOW_FRAGMENT).unwrap();
-        let fsb = fsource.to_bytes();
+    pub fn new(
+        renderer: &RefRenderer,
+        network: &RefNetworkClient,
+        clock: &timers::RefTimer,
+        config: &Config,
+        bindings: &Bindings,
+        room: RoomInfo,
+    ) -> RoomSceneContext {
+        let circle = Circle::new(0f32, 0f32, 10f32);
 
-        program.vertex_shader(vsb).fragment_shader(fsb).link();
+        let mut program = shaders::new(renderer);
+        program
+            .vertex_shader(smpl::BATCH_VERTEX)
+            .fragment_shader(smpl::BATCH_FRAGMENT)
+            .link();
 
         let translation = Matrix4::from_translation(Vector3 {
             x: 300f32,
@@ -49,99 +80,319 @@ impl RoomSceneContext {
         });
 
         RoomSceneContext {
+            room: room,
+            member: None,
+            members: Vec::new(),
             circle: circle,
+            previous: Transform::at(0f32, 0f32),
+            input_carry_ms: 0f32,
+            pending_inputs: VecDeque::new(),
+            next_input: 0,
+            last_input_ack: None,
+            avatars: HashMap::new(),
             program: program,
+            batch: RefCell::new(Batch::new(renderer)),
             matrix: ortho(0.0, 600.0, 0.0, 400.0, -1.0, 1.0) * translation,
-            gl: Box::new(gl.clone()),
-            timer: timers::new(),
-            input_state: InputState::default(),
+            renderer: renderer.clone(),
+            clock: clock.clone(),
+            input_state: InputState::new(bindings),
             network: network.clone(),
-            debug_move_start: 0,
-            debug_move_stop: 0,
+            config: config.clone(),
+            switch_context: None,
+        }
+    }
+
+    // Moves the local player at once and tells the server. Frames longer
+    // than the server accepts in one input are split.
+    fn predict(&mut self, direction: Vector2<f32>, dt_ms: f32) {
+        let total = self.input_carry_ms + dt_ms;
+        let mut whole = total.floor() as i64;
+        self.input_carry_ms = total - whole as f32;
+
+        while whole > 0 {
+            let duration = ::std::cmp::min(whole, MAX_INPUT_MS as i64);
+            whole -= duration;
+
+            let input = Input {
+                seq: self.next_input,
+                direction: direction,
+                duration_ms: duration as u16,
+            };
+            self.next_input = self.next_input.wrapping_add(1);
+
+            if let Err(e) = self.network.borrow_mut().send(Message::Input(input.clone())) {
+                println!("Network error: {:?}", e);
+            }
+
+            self.circle.transform.position = movement::apply(self.circle.transform.position, &input);
+            if self.pending_inputs.len() == MAX_PENDING_INPUTS {
+                self.pending_inputs.pop_front();
+            }
+            self.pending_inputs.push_back(input);
+        }
+    }
+
+    // Starts over from the server's position and replays the inputs it has
+    // not seen yet.
+    fn reconcile(&mut self, seq: u16, pos: Point2<f32>) {
+        if let Some(last) = self.last_input_ack {
+            if !movement::is_newer(seq, last) {
+                return;
+            }
+        }
+        self.last_input_ack = Some(seq);
+
+        self.pending_inputs
+            .retain(|input| movement::is_newer(input.seq, seq));
+
+        let mut predicted = pos;
+        for input in self.pending_inputs.iter() {
+            predicted = movement::apply(predicted, input);
+        }
+        self.circle.transform.position = predicted;
+    }
+
+    // Direction keys win over the pointer while any is held. Opposite keys
+    // cancel out, and diagonals are as fast as straight lines.
+    fn move_direction(&self) -> Option<Vector2<f32>> {
+        let axis = |positive: Action, negative: Action| {
+            self.input_state.held(positive) as i32 - self.input_state.held(negative) as i32
+        };
+        let dx = axis(Action::MoveRight, Action::MoveLeft);
+        let dy = axis(Action::MoveUp, Action::MoveDown);
+        if dx != 0 || dy != 0 {
+            return movement::direction(dx as f32, dy as f32);
+        }
+
+        if self.input_state.held(Action::Move) {
+            movement::direction(
+                self.input_state.mouse_x as f32 - 300f32,
+                400f32 - self.input_state.mouse_y as f32 - 200f32,
+            )
+        } else {
+            None
         }
     }
+
+    fn add_avatar(&mut self, id: u16) {
+        if Some(id) != self.member {
+            let avatar = Avatar::new(id);
+            self.avatars.insert(id, avatar);
+        }
+    }
+
+    fn back_to_lobby(&mut self) {
+        if self.switch_context.is_some() {
+            return;
+        }
+        self.switch_context = Some(Rc::new(RefCell::new(MainSceneContext::new(
+            &self.renderer,
+            &self.network,
+            &self.clock,
+            &self.config,
+            self.input_state.bindings(),
+        ))));
+    }
 }
 
 impl SceneContext for RoomSceneContext {
-    fn render(&self) {
-        unsafe {
-            self.gl.ClearColor(0.05, 0.05, 0.1, 1.0);
-            self.gl.Clear(COLOR_BUFFER_BIT);
+    fn render(&self, alpha: f32) {
+        self.renderer.borrow_mut().clear(0.05, 0.05, 0.1, 1.0);
 
-            self.program.use_program();
-            self.program.uniform_matrix4fv("supermatrix", &self.matrix);
+        // The view follows the local player, so it stays in the middle of the
+        // window.
+        let local = self.previous.lerp(&self.circle.transform, alpha);
+        let camera = Matrix4::from_translation(-local.position.to_vec().extend(0f32));
+        let view = self.matrix * camera;
 
-            self.circle.draw();
+        // Everyone shares one material, so the whole room is one draw.
+        let material = self.program.program();
+        let mut batch = self.batch.borrow_mut();
+        draw_grid(&mut batch, material, local.position);
+        self.circle
+            .draw_with(&mut batch, material, &local, LOCAL_COLOUR);
+        for avatar in self.avatars.values() {
+            let transform = avatar.previous.lerp(&avatar.circle.transform, alpha);
+            avatar
+                .circle
+                .draw_with(&mut batch, material, &transform, avatar.colour);
         }
+        batch.flush(&view);
     }
 
-    fn update(&mut self) {
-        let dt = self.timer.frame_time();
+    fn update(&mut self, dt: Duration) {
+        let dt_ms = timers::as_millis_f32(dt);
+        self.previous = self.circle.transform;
+
+        if !self.input_state.held(Action::Stop) {
+            if let Some(d) = self.move_direction() {
+                self.predict(d, dt_ms);
+            }
+        }
 
-        if self.input_state.mouse_rbtn_pressed {
-            let d: Vector2<f32> = Vector2 {
-                x: self.input_state.mouse_x as f32 - 300f32,
-                y: 400f32 - self.input_state.mouse_y as f32 - 200f32,
-            }.normalize();
+        if self.input_state.pressed(Action::Move) {
+            self.clock.borrow_mut().start_stopwatch("move");
+        }
 
-            let msg = MessageType::MemberMove(d.x, d.y);
-            self.network.send_to(&protocol::pack(&msg), "127.0.0.1:45000").unwrap();
+        if self.input_state.released(Action::Move) {
+            let moving = self.clock.borrow_mut().stop_stopwatch("move");
+            if let Some(moving) = moving {
+                println!("Moving elapsed time {:?}", moving);
+            }
+        }
 
-            self.circle.gpos += d * dt as f32;
+        if self.input_state.released(Action::Leave) {
+            self.back_to_lobby();
         }
 
-        if self.input_state.mouse_rbtn_was_pressed {
-            self.debug_move_start = self.timer.elapsed();
-            self.input_state.mouse_rbtn_was_pressed = false;
+        self.input_state.end_frame();
+
+        let delay = Duration::from_millis(self.config.interpolation_delay_ms as u64);
+        let render_time = self.clock.borrow().elapsed().checked_sub(delay);
+        for avatar in self.avatars.values_mut() {
+            avatar.previous = avatar.circle.transform;
+            if let Some(pos) = render_time.and_then(|time| avatar.snapshots.sample(time)) {
+                avatar.circle.transform.position = pos;
+            }
         }
+    }
 
-        if self.input_state.mouse_rbtn_was_released {
-            self.debug_move_stop = self.timer.elapsed();
-            println!("Moving elapsed time {:?}", self.debug_move_stop - self.debug_move_start);
-            let msg = MessageType::MemberStopMove;
-            self.network.send_to(&protocol::pack(&msg), "127.0.0.1:45000").unwrap();
-            self.input_state.mouse_rbtn_was_released = false;
+    fn user_input(&mut self, events: Vec<Event>) {
+        for event in events.iter() {
+            self.input_state.handle(event);
         }
     }
 
-    fn user_input(&mut self, event: Event) {
-        match event {
-            Event::MouseButtonDown { x, y, mouse_btn, .. } => {
-                match mouse_btn {
-                    MouseButton::Right => {
-                        self.input_state.mouse_rbtn_pressed = true;
-                        self.input_state.mouse_rbtn_was_pressed = true;
-                        self.input_state.mouse_x = x;
-                        self.input_state.mouse_y = y;
+    fn network_input(&mut self, msg: Message) {
+        match msg {
+            Message::RoomJoined {
+                room,
+                member,
+                members,
+            } => {
+                if room.id == self.room.id {
+                    println!("Joined {:?} as member {}, with {:?}", room.name, member, members);
+                    self.room = room;
+                    self.member = Some(member);
+                    // The server puts new members at the origin.
+                    self.circle.transform.position = Point2::origin();
+                    self.pending_inputs.clear();
+                    self.last_input_ack = None;
+                    self.avatars.clear();
+                    for &id in members.iter() {
+                        self.add_avatar(id);
                     }
+                    self.members = members;
+                }
+            }
+
+            Message::JoinRefused(id, reason) => {
+                if id == self.room.id {
+                    println!("Couldn't join {:?}: {}", self.room.name, reason);
+                    self.back_to_lobby();
+                }
+            }
+
+            Message::MemberJoined(member) => {
+                if !self.members.contains(&member) {
+                    self.members.push(member);
+                    self.add_avatar(member);
+                }
+            }
+
+            Message::MemberLeft(member) => {
+                self.members.retain(|&id| id != member);
+                self.avatars.remove(&member);
+            }
 
-                    _ => (),
+            Message::InputAck { seq, pos } => {
+                if self.member.is_some() {
+                    self.reconcile(seq, pos);
                 }
             }
 
-            Event::MouseButtonUp { x, y, mouse_btn, .. } => {
-                match mouse_btn {
-                    MouseButton::Right => {
-                        self.input_state.mouse_rbtn_pressed = false;
-                        self.input_state.mouse_rbtn_was_released = true;
-                        self.input_state.mouse_x = x;
-                        self.input_state.mouse_y = y;
+            // Only members we were told about are drawn; a late state for
+            // someone who already left must not bring them back.
+            Message::RoomState(states) => {
+                let now = self.clock.borrow().elapsed();
+                for state in states {
+                    if let Some(avatar) = self.avatars.get_mut(&state.id) {
+                        // Until the interpolation delay has passed, show a
+                        // new member where it was first seen, not at the
+                        // origin.
+                        if avatar.snapshots.is_empty() {
+                            avatar.circle.transform.position = state.pos;
+                            avatar.previous = avatar.circle.transform;
+                        }
+                        avatar.snapshots.push(now, state.pos);
                     }
+                }
+            }
 
-                    _ => (),
+            Message::RoomUpdated(room) => {
+                if room.id == self.room.id {
+                    self.room = room;
                 }
             }
 
-            Event::MouseMotion { x, y, .. } => {
-                self.input_state.mouse_x = x;
-                self.input_state.mouse_y = y;
+            Message::RoomRemoved(id) => {
+                if id == self.room.id {
+                    println!("{:?} was closed", self.room.name);
+                    self.back_to_lobby();
+                }
             }
 
             _ => (),
         }
     }
 
+    // A new session starts outside any room, so there is nothing left to
+    // stay for.
+    fn connection_changed(&mut self, state: ConnectionState) {
+        if state != ConnectionState::Connected {
+            self.member = None;
+            self.members.clear();
+            self.avatars.clear();
+            self.back_to_lobby();
+        }
+    }
+
     fn switch_context(&self) -> Option<RefSceneContext> {
-        None
+        match self.switch_context {
+            Some(ref context) => Some(context.clone()),
+            None => None,
+        }
+    }
+}
+
+// Lines fixed in world space across the window around `centre`, so the
+// ground visibly passes under the player while the view follows it.
+fn draw_grid(batch: &mut Batch, material: ProgramId, centre: Point2<f32>) {
+    let (left, right) = (centre.x - 300f32, centre.x + 300f32);
+    let (bottom, top) = (centre.y - 200f32, centre.y + 200f32);
+
+    let mut x = (left / GRID_SPACING).floor() * GRID_SPACING;
+    while x <= right {
+        let (from, to) = (Point2::new(x, bottom), Point2::new(x, top));
+        batch.line(material, from, to, 1f32, GRID_COLOUR);
+        x += GRID_SPACING;
+    }
+    let mut y = (bottom / GRID_SPACING).floor() * GRID_SPACING;
+    while y <= top {
+        let (from, to) = (Point2::new(left, y), Point2::new(right, y));
+        batch.line(material, from, to, 1f32, GRID_COLOUR);
+        y += GRID_SPACING;
+    }
+}
+
+// The scene only lives as long as we are in the room, so leaving it by any
+// path (Escape, window close, panic) tells the server we left.
+impl Drop for RoomSceneContext {
+    fn drop(&mut self) {
+        if let Ok(mut network) = self.network.try_borrow_mut() {
+            if let Err(e) = network.send_reliable(Message::LeaveRoom) {
+                println!("Network error: {:?}", e);
+            }
+        }
     }
 }
@@ -1,12 +1,19 @@
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::time::Duration;
 use sdl2::event::Event;
+use rust_chunk::network::{ConnectionState, Message};
 
 pub type RefSceneContext = Rc<RefCell<SceneContext>>;
 
 pub trait SceneContext {
-    fn render(&self);
-    fn update(&mut self);
-    fn user_input(&mut self, event: Event);
+    // `alpha` is how far the present lies between the last two updates.
+    fn render(&self, alpha: f32);
+    // Advances the scene by one fixed simulation step.
+    fn update(&mut self, dt: Duration);
+    // Every input event since the last frame, oldest first.
+    fn user_input(&mut self, events: Vec<Event>);
+    fn network_input(&mut self, msg: Message);
+    fn connection_changed(&mut self, state: ConnectionState);
     fn switch_context(&self) -> Option<RefSceneContext>;
 }
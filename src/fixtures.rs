@@ -0,0 +1,68 @@
+// Made-up server traffic for screenshot runs, so every screenshot shows the
+// same rooms and members and needs no server.
+
+use cgmath::Point2;
+use config::Scene;
+use network::{ConnectionState, MemberState, Message, NetworkEvent, OfflineNetwork, RoomInfo};
+
+const ROOMS: [(&'static str, u16, u16); 7] = [
+    ("Lobby", 16, 5),
+    ("Sandbox", 8, 1),
+    ("Racing", 4, 4),
+    ("Quiet corner", 2, 0),
+    ("Team red", 6, 4),
+    ("Team blue", 6, 2),
+    ("Late night", 10, 7),
+];
+
+// Where the other members of `room` stand, around the local player at the
+// origin.
+const MEMBERS: [(u16, f32, f32); 3] = [(2, -120.0, 60.0), (3, 90.0, -80.0), (4, 160.0, 120.0)];
+const LOCAL_MEMBER: u16 = 1;
+
+pub fn rooms() -> Vec<RoomInfo> {
+    ROOMS
+        .iter()
+        .enumerate()
+        .map(|(i, &(name, capacity, members))| RoomInfo {
+            id: i as u16 + 1,
+            name: name.to_string(),
+            capacity: capacity,
+            members: members,
+        })
+        .collect()
+}
+
+// The room a room screenshot is taken in.
+pub fn room() -> RoomInfo {
+    rooms().remove(4)
+}
+
+// A connection that has already delivered what `scene` shows.
+pub fn network(scene: Scene) -> OfflineNetwork {
+    let mut network = OfflineNetwork::new();
+    network.deliver(NetworkEvent::StateChanged(ConnectionState::Connected));
+    match scene {
+        Scene::Lobby => {
+            network.deliver(NetworkEvent::Message(Message::RoomList(rooms())));
+        }
+        Scene::Room => {
+            let mut members = vec![LOCAL_MEMBER];
+            members.extend(MEMBERS.iter().map(|&(id, _, _)| id));
+            network.deliver(NetworkEvent::Message(Message::RoomJoined {
+                room: room(),
+                member: LOCAL_MEMBER,
+                members: members,
+            }));
+            let states = MEMBERS
+                .iter()
+                .map(|&(id, x, y)| MemberState {
+                    id: id,
+                    pos: Point2::new(x, y),
+                })
+                .collect();
+            network.deliver(NetworkEvent::Message(Message::RoomState(states)));
+        }
+    }
+    network
+}
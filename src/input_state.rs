@@ -1,20 +1,410 @@
+use rust_chunk::config::Config;
+use sdl2::controller::Button;
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use std::collections::HashSet;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Move,
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Stop,
+    CastSkill,
+    OpenChat,
+    Leave,
+    Screenshot,
+}
+
+const ACTIONS: &'static [(&'static str, Action)] = &[
+    ("move", Action::Move),
+    ("move_up", Action::MoveUp),
+    ("move_down", Action::MoveDown),
+    ("move_left", Action::MoveLeft),
+    ("move_right", Action::MoveRight),
+    ("stop", Action::Stop),
+    ("cast_skill", Action::CastSkill),
+    ("open_chat", Action::OpenChat),
+    ("leave", Action::Leave),
+    ("screenshot", Action::Screenshot),
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Input {
+    Key(Keycode),
+    Mouse(MouseButton),
+    Pad(Button),
+}
+
+impl Input {
+    // `key:<SDL key name>`, `mouse:<left|middle|right|x1|x2>` or
+    // `pad:<SDL controller button name>`, e.g. `key:Escape`, `pad:a`.
+    pub fn parse(value: &str) -> Result<Input, String> {
+        let mut parts = value.splitn(2, ':');
+        let device = parts.next().unwrap().trim();
+        let name = parts.next().unwrap_or("").trim();
+        let input = match device {
+            "key" => Keycode::from_name(name).map(Input::Key),
+            "mouse" => mouse_button(name).map(Input::Mouse),
+            "pad" => Button::from_string(name).map(Input::Pad),
+            _ => return Err(format!("unknown input device in {:?}", value)),
+        };
+        input.ok_or_else(|| format!("unknown input {:?}", value))
+    }
+}
+
+fn mouse_button(name: &str) -> Option<MouseButton> {
+    match name {
+        "left" => Some(MouseButton::Left),
+        "middle" => Some(MouseButton::Middle),
+        "right" => Some(MouseButton::Right),
+        "x1" => Some(MouseButton::X1),
+        "x2" => Some(MouseButton::X2),
+        _ => None,
+    }
+}
+
+// Which inputs trigger which action. Several inputs may share an action and
+// one input may trigger several.
+#[derive(Clone, Debug)]
+pub struct Bindings {
+    bindings: Vec<(Input, Action)>,
+}
+
+impl Bindings {
+    pub fn default() -> Bindings {
+        Bindings {
+            bindings: vec![
+                (Input::Mouse(MouseButton::Right), Action::Move),
+                (Input::Key(Keycode::W), Action::MoveUp),
+                (Input::Key(Keycode::Up), Action::MoveUp),
+                (Input::Pad(Button::DPadUp), Action::MoveUp),
+                (Input::Key(Keycode::S), Action::MoveDown),
+                (Input::Key(Keycode::Down), Action::MoveDown),
+                (Input::Pad(Button::DPadDown), Action::MoveDown),
+                (Input::Key(Keycode::A), Action::MoveLeft),
+                (Input::Key(Keycode::Left), Action::MoveLeft),
+                (Input::Pad(Button::DPadLeft), Action::MoveLeft),
+                (Input::Key(Keycode::D), Action::MoveRight),
+                (Input::Key(Keycode::Right), Action::MoveRight),
+                (Input::Pad(Button::DPadRight), Action::MoveRight),
+                (Input::Key(Keycode::H), Action::Stop),
+                (Input::Key(Keycode::Q), Action::CastSkill),
+                (Input::Key(Keycode::Return), Action::OpenChat),
+                (Input::Key(Keycode::Escape), Action::Leave),
+                (Input::Pad(Button::Back), Action::Leave),
+                (Input::Key(Keycode::F12), Action::Screenshot),
+            ],
+        }
+    }
+
+    // Each `bind.<action> = <input>, <input>...` line replaces every default
+    // binding of that action; an empty list leaves it unbound.
+    pub fn from_config(config: &Config) -> Result<Bindings, String> {
+        let mut bindings = Bindings::default();
+        for &(ref name, ref value) in config.bindings.iter() {
+            let action = match ACTIONS.iter().find(|&&(action, _)| action == name) {
+                Some(&(_, action)) => action,
+                None => return Err(format!("unknown action {:?} in bind.{}", name, name)),
+            };
+            let mut inputs = Vec::new();
+            for input in value.split(',').map(|input| input.trim()) {
+                if !input.is_empty() {
+                    inputs.push(Input::parse(input).map_err(|e| format!("bind.{}: {}", name, e))?);
+                }
+            }
+            bindings.bind(action, &inputs);
+        }
+        Ok(bindings)
+    }
+
+    pub fn bind(&mut self, action: Action, inputs: &[Input]) {
+        self.bindings.retain(|&(_, bound)| bound != action);
+        for &input in inputs {
+            self.bindings.push((input, action));
+        }
+    }
+
+    // Actions an event starts, for the few handled outside any scene.
+    pub fn started_by(&self, event: &Event) -> Vec<Action> {
+        match *event {
+            Event::KeyDown {
+                keycode: Some(keycode),
+                repeat: false,
+                ..
+            } => self.actions(Input::Key(keycode)),
+            Event::MouseButtonDown { mouse_btn, .. } => self.actions(Input::Mouse(mouse_btn)),
+            Event::ControllerButtonDown { button, .. } => self.actions(Input::Pad(button)),
+            _ => Vec::new(),
+        }
+    }
+
+    fn actions(&self, input: Input) -> Vec<Action> {
+        self.bindings
+            .iter()
+            .filter(|&&(bound, _)| bound == input)
+            .map(|&(_, action)| action)
+            .collect()
+    }
+}
+
+// Action state built from input events. `pressed` and `released` cover
+// everything since the last `end_frame`, so a press and release that land in
+// the same frame are both seen.
 #[derive(Debug)]
 pub struct InputState {
-    pub mouse_rbtn_pressed: bool,
-    pub mouse_rbtn_was_pressed: bool,
-    pub mouse_rbtn_was_released: bool,
+    bindings: Bindings,
+    down: HashSet<Input>,
+    pressed: HashSet<Action>,
+    released: HashSet<Action>,
     pub mouse_x: i32,
     pub mouse_y: i32,
 }
 
 impl InputState {
-    pub fn default() -> InputState {
+    pub fn new(bindings: &Bindings) -> InputState {
         InputState {
-            mouse_rbtn_pressed: false,
-            mouse_rbtn_was_pressed: false,
-            mouse_rbtn_was_released: false,
+            bindings: bindings.clone(),
+            down: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
             mouse_x: 0,
             mouse_y: 0,
         }
     }
+
+    pub fn handle(&mut self, event: &Event) {
+        match *event {
+            Event::KeyDown {
+                keycode: Some(keycode),
+                repeat: false,
+                ..
+            } => self.input_down(Input::Key(keycode)),
+            Event::KeyUp {
+                keycode: Some(keycode),
+                ..
+            } => self.input_up(Input::Key(keycode)),
+
+            Event::MouseButtonDown { x, y, mouse_btn, .. } => {
+                self.mouse_x = x;
+                self.mouse_y = y;
+                self.input_down(Input::Mouse(mouse_btn));
+            }
+            Event::MouseButtonUp { x, y, mouse_btn, .. } => {
+                self.mouse_x = x;
+                self.mouse_y = y;
+                self.input_up(Input::Mouse(mouse_btn));
+            }
+            Event::MouseMotion { x, y, .. } => {
+                self.mouse_x = x;
+                self.mouse_y = y;
+            }
+
+            Event::ControllerButtonDown { button, .. } => self.input_down(Input::Pad(button)),
+            Event::ControllerButtonUp { button, .. } => self.input_up(Input::Pad(button)),
+
+            _ => (),
+        }
+    }
+
+    fn input_down(&mut self, input: Input) {
+        for action in self.bindings.actions(input) {
+            if !self.held(action) {
+                self.pressed.insert(action);
+            }
+        }
+        self.down.insert(input);
+    }
+
+    fn input_up(&mut self, input: Input) {
+        if !self.down.remove(&input) {
+            return;
+        }
+        for action in self.bindings.actions(input) {
+            if !self.held(action) {
+                self.released.insert(action);
+            }
+        }
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    // While any input bound to `action` is down.
+    pub fn held(&self, action: Action) -> bool {
+        self.down
+            .iter()
+            .any(|&input| self.bindings.actions(input).contains(&action))
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn released(&self, action: Action) -> bool {
+        self.released.contains(&action)
+    }
+
+    pub fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdl2::keyboard::Mod;
+
+    fn key_down(keycode: Keycode) -> Event {
+        Event::KeyDown {
+            timestamp: 0,
+            window_id: 0,
+            keycode: Some(keycode),
+            scancode: None,
+            keymod: Mod::empty(),
+            repeat: false,
+        }
+    }
+
+    fn key_up(keycode: Keycode) -> Event {
+        Event::KeyUp {
+            timestamp: 0,
+            window_id: 0,
+            keycode: Some(keycode),
+            scancode: None,
+            keymod: Mod::empty(),
+            repeat: false,
+        }
+    }
+
+    fn mouse_down(mouse_btn: MouseButton) -> Event {
+        Event::MouseButtonDown {
+            timestamp: 0,
+            window_id: 0,
+            which: 0,
+            mouse_btn: mouse_btn,
+            clicks: 1,
+            x: 10,
+            y: 20,
+        }
+    }
+
+    fn mouse_up(mouse_btn: MouseButton) -> Event {
+        Event::MouseButtonUp {
+            timestamp: 0,
+            window_id: 0,
+            which: 0,
+            mouse_btn: mouse_btn,
+            clicks: 1,
+            x: 30,
+            y: 40,
+        }
+    }
+
+    fn configured(lines: &[(&str, &str)]) -> Result<Bindings, String> {
+        let mut config = Config::default();
+        config.bindings = lines
+            .iter()
+            .map(|&(action, inputs)| (action.to_string(), inputs.to_string()))
+            .collect();
+        Bindings::from_config(&config)
+    }
+
+    #[test]
+    fn inputs_parse_by_device() {
+        assert_eq!(Input::parse("key:Escape"), Ok(Input::Key(Keycode::Escape)));
+        assert_eq!(
+            Input::parse(" mouse : right "),
+            Ok(Input::Mouse(MouseButton::Right))
+        );
+        assert_eq!(Input::parse("pad:a"), Ok(Input::Pad(Button::A)));
+
+        assert!(Input::parse("joystick:1")
+            .unwrap_err()
+            .contains("unknown input device"));
+        assert!(Input::parse("key:NoSuchKey")
+            .unwrap_err()
+            .contains("unknown input"));
+        assert!(Input::parse("mouse").is_err());
+    }
+
+    #[test]
+    fn config_lines_replace_an_actions_bindings() {
+        let bindings = configured(&[("leave", "key:Q, pad:b"), ("stop", "")]).unwrap();
+        assert_eq!(
+            bindings.actions(Input::Key(Keycode::Q)),
+            vec![Action::CastSkill, Action::Leave]
+        );
+        assert_eq!(bindings.actions(Input::Pad(Button::B)), vec![Action::Leave]);
+        assert!(bindings.actions(Input::Key(Keycode::Escape)).is_empty());
+        // An empty list unbinds.
+        assert!(bindings.actions(Input::Key(Keycode::H)).is_empty());
+        // Other actions keep their defaults.
+        assert_eq!(
+            bindings.actions(Input::Key(Keycode::W)),
+            vec![Action::MoveUp]
+        );
+    }
+
+    #[test]
+    fn config_errors_name_the_line() {
+        assert_eq!(
+            configured(&[("dance", "key:D")]).unwrap_err(),
+            "unknown action \"dance\" in bind.dance"
+        );
+        assert!(configured(&[("leave", "key:Escape, glove:1")])
+            .unwrap_err()
+            .starts_with("bind.leave: "));
+    }
+
+    #[test]
+    fn actions_are_pressed_once_and_released_by_the_last_input() {
+        let mut bindings = Bindings::default();
+        bindings.bind(
+            Action::Move,
+            &[Input::Mouse(MouseButton::Right), Input::Key(Keycode::M)],
+        );
+        let mut state = InputState::new(&bindings);
+
+        state.handle(&mouse_down(MouseButton::Right));
+        assert!(state.pressed(Action::Move));
+        assert!(state.held(Action::Move));
+        assert_eq!((state.mouse_x, state.mouse_y), (10, 20));
+        state.end_frame();
+        assert!(!state.pressed(Action::Move));
+
+        // Already held, so the second input presses nothing.
+        state.handle(&key_down(Keycode::M));
+        assert!(!state.pressed(Action::Move));
+
+        state.handle(&mouse_up(MouseButton::Right));
+        assert!(!state.released(Action::Move));
+        assert!(state.held(Action::Move));
+
+        state.handle(&key_up(Keycode::M));
+        assert!(state.released(Action::Move));
+        assert!(!state.held(Action::Move));
+    }
+
+    #[test]
+    fn press_and_release_in_one_frame_are_both_seen() {
+        let mut state = InputState::new(&Bindings::default());
+        state.handle(&key_down(Keycode::Q));
+        state.handle(&key_up(Keycode::Q));
+        assert!(state.pressed(Action::CastSkill));
+        assert!(state.released(Action::CastSkill));
+        assert!(!state.held(Action::CastSkill));
+    }
+
+    #[test]
+    fn releases_without_a_press_are_ignored() {
+        let mut state = InputState::new(&Bindings::default());
+        state.handle(&key_up(Keycode::Escape));
+        assert!(!state.released(Action::Leave));
+    }
 }
@@ -0,0 +1,158 @@
+use cgmath::{EuclideanSpace, InnerSpace, Point2};
+use std::collections::VecDeque;
+use std::time::Duration;
+use timers;
+
+// Enough for over a second of server ticks at the default rate.
+const MAX_SNAPSHOTS: usize = 32;
+// When snapshots stop arriving a remote player keeps moving along its last
+// known velocity for at most this long, then stops and waits.
+pub const MAX_EXTRAPOLATION_MS: u64 = 250;
+
+pub struct Snapshot {
+    pub time: Duration,
+    pub pos: Point2<f32>,
+}
+
+// Positions of one remote entity, stamped with `timers::Timer::elapsed` when
+// they arrived, oldest first.
+pub struct SnapshotBuffer {
+    snapshots: VecDeque<Snapshot>,
+}
+
+impl SnapshotBuffer {
+    pub fn new() -> SnapshotBuffer {
+        SnapshotBuffer {
+            snapshots: VecDeque::with_capacity(MAX_SNAPSHOTS),
+        }
+    }
+
+    pub fn push(&mut self, time: Duration, pos: Point2<f32>) {
+        if let Some(newest) = self.snapshots.back() {
+            if time < newest.time {
+                return;
+            }
+        }
+        if self.snapshots.len() == MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(Snapshot {
+            time: time,
+            pos: pos,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    // Where the entity was at `time`, which the caller sets some delay behind
+    // the present so there is usually a snapshot on either side of it.
+    pub fn sample(&mut self, time: Duration) -> Option<Point2<f32>> {
+        // Snapshots entirely in the past are no longer needed, but the newest
+        // two are kept to extrapolate from.
+        while self.snapshots.len() > 2 && self.snapshots[1].time <= time {
+            self.snapshots.pop_front();
+        }
+
+        let (from, to) = match self.snapshots.len() {
+            0 => return None,
+            1 => return Some(self.snapshots[0].pos),
+            _ => (&self.snapshots[0], &self.snapshots[1]),
+        };
+        if time <= from.time {
+            return Some(from.pos);
+        }
+        if to.time <= from.time {
+            return Some(to.pos);
+        }
+
+        let span = timers::as_millis_f32(to.time - from.time);
+        if time <= to.time {
+            let t = timers::as_millis_f32(time - from.time) / span;
+            return Some(Point2::from_vec(from.pos.to_vec().lerp(to.pos.to_vec(), t)));
+        }
+
+        let ahead = ::std::cmp::min(time - to.time, Duration::from_millis(MAX_EXTRAPOLATION_MS));
+        let ahead = timers::as_millis_f32(ahead);
+        let velocity = (to.pos - from.pos) / span;
+        Some(to.pos + velocity * ahead)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn buffer(snapshots: &[(u64, f32, f32)]) -> SnapshotBuffer {
+        let mut buffer = SnapshotBuffer::new();
+        for &(time, x, y) in snapshots.iter() {
+            buffer.push(ms(time), Point2::new(x, y));
+        }
+        buffer
+    }
+
+    #[test]
+    fn nothing_to_sample_until_the_first_snapshot() {
+        let mut buffer = SnapshotBuffer::new();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.sample(ms(100)), None);
+    }
+
+    #[test]
+    fn samples_between_snapshots_are_interpolated() {
+        let mut buffer = buffer(&[(0, 0.0, 0.0), (100, 10.0, 20.0)]);
+        assert_eq!(buffer.sample(ms(50)), Some(Point2::new(5.0, 10.0)));
+        assert_eq!(buffer.sample(ms(100)), Some(Point2::new(10.0, 20.0)));
+    }
+
+    #[test]
+    fn samples_before_the_first_snapshot_stay_there() {
+        let mut buffer = buffer(&[(100, 1.0, 2.0), (200, 3.0, 4.0)]);
+        assert_eq!(buffer.sample(ms(20)), Some(Point2::new(1.0, 2.0)));
+    }
+
+    #[test]
+    fn extrapolation_stops_after_the_limit() {
+        let mut buffer = buffer(&[(0, 0.0, 0.0), (100, 10.0, 0.0)]);
+        assert_eq!(buffer.sample(ms(200)), Some(Point2::new(20.0, 0.0)));
+        let limit = 100 + MAX_EXTRAPOLATION_MS;
+        assert_eq!(buffer.sample(ms(limit)), Some(Point2::new(35.0, 0.0)));
+        assert_eq!(
+            buffer.sample(ms(limit + 1000)),
+            Some(Point2::new(35.0, 0.0))
+        );
+    }
+
+    #[test]
+    fn snapshots_from_the_past_are_rejected() {
+        let mut buffer = buffer(&[(100, 1.0, 1.0), (50, 2.0, 2.0)]);
+        assert_eq!(buffer.snapshots.len(), 1);
+        assert_eq!(buffer.sample(ms(100)), Some(Point2::new(1.0, 1.0)));
+    }
+
+    #[test]
+    fn equal_timestamps_take_the_newer_position() {
+        let mut buffer = buffer(&[(100, 1.0, 1.0), (100, 2.0, 2.0)]);
+        assert_eq!(buffer.sample(ms(150)), Some(Point2::new(2.0, 2.0)));
+    }
+
+    #[test]
+    fn old_snapshots_are_dropped() {
+        let mut buffer = SnapshotBuffer::new();
+        for i in 0..MAX_SNAPSHOTS as u64 + 8 {
+            buffer.push(ms(i * 50), Point2::new(i as f32, 0.0));
+        }
+        assert_eq!(buffer.snapshots.len(), MAX_SNAPSHOTS);
+        assert_eq!(buffer.snapshots[0].time, ms(8 * 50));
+
+        // Sampling keeps only the two around the sample time.
+        buffer.sample(ms(30 * 50 + 25));
+        assert_eq!(buffer.snapshots.len(), MAX_SNAPSHOTS + 8 - 30);
+        assert_eq!(buffer.snapshots[0].time, ms(30 * 50));
+    }
+}
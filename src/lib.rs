@@ -0,0 +1,14 @@
+extern crate cgmath;
+extern crate chunk_protocol as protocol;
+extern crate collision;
+extern crate gfx_gl;
+
+pub mod config;
+pub mod fixtures;
+pub mod interpolation;
+pub mod movement;
+pub mod network;
+pub mod render;
+pub mod rooms_ui;
+pub mod server;
+pub mod timers;
@@ -2,29 +2,109 @@ extern crate cgmath;
 extern crate chunk_protocol as protocol;
 extern crate collision;
 extern crate gfx_gl;
+extern crate rust_chunk;
 extern crate sdl2;
-extern crate time;
 
-use sdl2::event::Event;
+use sdl2::EventPump;
+use sdl2::event::{Event, WindowEvent};
 // use sdl2::keyboard::Keycode;
 use gfx_gl::*;
+use sdl2::video::SwapInterval;
 // use gfx_gl::types::*;
-use std::net::UdpSocket;
-use protocol::enums::MessageType;
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use context::*;
+use input_state::{Action, Bindings};
+use rust_chunk::config::{Config, Scene};
+use rust_chunk::fixtures;
+use rust_chunk::network::{Message, NetworkClient, NetworkEvent, RefNetworkClient};
+use rust_chunk::render::{png, GlRenderer, RefRenderer};
+use rust_chunk::timers;
 
 mod shaders;
-mod timers;
 // mod skills;
 // mod threads;
-mod rooms_ui;
 mod graphics;
 mod context;
 mod objects;
 mod input_state;
 
+// Frames the frame rate in the window title is averaged over.
+const FRAME_HISTORY: usize = 120;
+
+// Everything SDL has queued since the last frame, in order. Each run of
+// mouse motion is folded into its last event, so a flood of motion never
+// holds back the clicks behind it.
+fn poll_events(event_pump: &mut EventPump) -> Vec<Event> {
+    let mut events: Vec<Event> = Vec::new();
+    for event in event_pump.poll_iter() {
+        let event = match (events.last(), event) {
+            (
+                Some(&Event::MouseMotion {
+                    xrel: last_xrel,
+                    yrel: last_yrel,
+                    ..
+                }),
+                Event::MouseMotion {
+                    timestamp,
+                    window_id,
+                    which,
+                    mousestate,
+                    x,
+                    y,
+                    xrel,
+                    yrel,
+                },
+            ) => {
+                events.pop();
+                Event::MouseMotion {
+                    timestamp: timestamp,
+                    window_id: window_id,
+                    which: which,
+                    mousestate: mousestate,
+                    x: x,
+                    y: y,
+                    xrel: last_xrel + xrel,
+                    yrel: last_yrel + yrel,
+                }
+            }
+            (_, event) => event,
+        };
+        events.push(event);
+    }
+    events
+}
+
+// Saves the frame just drawn, before it is swapped out.
+fn save_screenshot(renderer: &RefRenderer, window: &sdl2::video::Window, path: &str) -> bool {
+    let (width, height) = window.drawable_size();
+    let pixels = renderer.borrow_mut().read_pixels(width, height);
+    match png::write(path, width, height, &pixels) {
+        Ok(()) => {
+            println!("Saved screenshot {}", path);
+            true
+        }
+        Err(e) => {
+            println!("Couldn't save screenshot {}: {}", path, e);
+            false
+        }
+    }
+}
+
+fn screenshot_name() -> String {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::new(0, 0));
+    format!(
+        "screenshot-{}{:03}.png",
+        since_epoch.as_secs(),
+        since_epoch.subsec_nanos() / 1_000_000
+    )
+}
+
 fn ortho2d(left: f32, right: f32, bottom: f32, top: f32) -> Vec<f32> {
     let a1 = 2.0 / (right - left);
     let a2 = 2.0 / (top - bottom);
@@ -38,18 +118,43 @@ fn ortho2d(left: f32, right: f32, bottom: f32, top: f32) -> Vec<f32> {
 }
 
 fn main() {
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    let bindings = match Bindings::from_config(&config) {
+        Ok(bindings) => bindings,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
     let sdl_context = sdl2::init().unwrap();
 
     let mut event_pump = sdl_context.event_pump().unwrap();
 
     let video_subsys = sdl_context.video().unwrap();
 
-    let window = video_subsys
-        .window("Title", 600, 400)
-        .opengl()
-        .resizable()
-        .build()
-        .unwrap();
+    // Controllers only send button events while open. SDL announces the ones
+    // already plugged in as added, too.
+    let controller_subsys = sdl_context.game_controller().unwrap();
+    let mut controllers = HashMap::new();
+
+    // A screenshot run still needs a window for its GL context, but never
+    // shows it. With SDL's offscreen video driver it needs no display at all.
+    let mut window_builder = video_subsys.window("Title", 600, 400);
+    window_builder.opengl();
+    if config.screenshot.is_some() {
+        window_builder.hidden();
+    } else {
+        window_builder.resizable();
+    }
+    let mut window = window_builder.build().unwrap();
 
     video_subsys
         .gl_attr()
@@ -60,6 +165,15 @@ fn main() {
     let gl_context = window.gl_create_context().unwrap();
     window.gl_make_current(&gl_context).unwrap();
 
+    let interval = if config.vsync {
+        SwapInterval::VSync
+    } else {
+        SwapInterval::Immediate
+    };
+    if !video_subsys.gl_set_swap_interval(interval) {
+        println!("Couldn't set swap interval {:?}", interval);
+    }
+
     let gl = Gl::load_with(|s| unsafe { std::mem::transmute(video_subsys.gl_get_proc_address(s)) });
 
     unsafe {
@@ -71,53 +185,186 @@ fn main() {
         gl.Enable(MULTISAMPLE);
     }
 
+    let renderer: RefRenderer = Rc::new(RefCell::new(GlRenderer::new(&gl)));
+
+    // What a hidden window holds is undefined on a real display server, so a
+    // screenshot run draws into a framebuffer of its own and saves that.
+    let screenshot_target = match config.screenshot {
+        Some(_) => {
+            let (width, height) = window.drawable_size();
+            let framebuffer = renderer.borrow_mut().create_framebuffer(width, height);
+            renderer.borrow_mut().bind_framebuffer(Some(framebuffer));
+            Some(framebuffer)
+        }
+        None => None,
+    };
+
     let mut exit = false;
 
-    let socket = UdpSocket::bind("127.0.0.1:45001").expect("couldn't bind to address");
-    socket
-        .set_nonblocking(true)
-        .expect("couldn't set nonblocking");
-    let network_source = Rc::new(socket);
-
-    // Connect to the server
-    {
-        let msg = MessageType::AddToListenersRequest;
-        let buf = protocol::pack(&msg);
-        network_source.send_to(&buf, "127.0.0.1:45000").unwrap();
-    }
+    // Screenshots show fixture data rather than whatever a server has.
+    let network: RefNetworkClient = match config.screenshot {
+        Some(_) => Rc::new(RefCell::new(fixtures::network(config.screenshot_scene))),
+        None => NetworkClient::open(&config)
+            .expect("couldn't open connection")
+            .shared(),
+    };
+
+    // Game time for the simulation and the scenes. It stops while the window
+    // is in the background, and for good in screenshot runs, so that their
+    // frame does not depend on how long startup took.
+    let clock = match config.screenshot {
+        Some(_) => timers::stopped(),
+        None => timers::shared(),
+    };
+
+    let mut active_scene_context: RefSceneContext = match config.screenshot {
+        Some(_) if config.screenshot_scene == Scene::Room => {
+            Rc::new(RefCell::new(RoomSceneContext::new(
+                &renderer, &network, &clock, &config, &bindings, fixtures::room(),
+            )))
+        }
+        _ => Rc::new(RefCell::new(MainSceneContext::new(
+            &renderer, &network, &clock, &config, &bindings,
+        ))),
+    };
+    let mut exit_code = 0;
 
-    let mut active_scene_context: RefSceneContext =
-        Rc::new(RefCell::new(MainSceneContext::new(&gl, &network_source)));
+    let mut sim = timers::FixedStep::new(config.sim_rate);
+    let mut frames = timers::FrameTimes::new(FRAME_HISTORY);
+    let mut last_report = Instant::now();
+    let frame_cap = if config.max_fps > 0 {
+        Some(Duration::new(0, 1_000_000_000 / config.max_fps))
+    } else {
+        None
+    };
 
     while !exit {
-        match event_pump.poll_event() {
-            Some(event) => {
-                match event {
-                    Event::Quit { .. } => {
-                        exit = true;
-                    }
+        let frame_start = Instant::now();
+
+        let mut screenshot = None;
+        let events = poll_events(&mut event_pump);
+        for event in events.iter() {
+            if bindings.started_by(event).contains(&Action::Screenshot) {
+                screenshot = Some(screenshot_name());
+            }
 
-                    _ => (),
+            match *event {
+                Event::Quit { .. } => {
+                    exit = true;
                 }
 
-                active_scene_context.borrow_mut().user_input(event);
+                Event::Window { win_event: WindowEvent::FocusLost, .. } => clock.borrow_mut().pause(),
+                Event::Window { win_event: WindowEvent::FocusGained, .. } if config.screenshot.is_none() => {
+                    clock.borrow_mut().resume()
+                }
+
+                Event::ControllerDeviceAdded { which, .. } => match controller_subsys.open(which) {
+                    Ok(controller) => {
+                        println!("Controller {} connected", controller.name());
+                        controllers.insert(controller.instance_id(), controller);
+                    }
+                    Err(e) => println!("Couldn't open controller {}: {:?}", which, e),
+                },
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    controllers.remove(&which);
+                }
+
+                _ => (),
             }
-            None => (),
         }
+        if !events.is_empty() {
+            active_scene_context.borrow_mut().user_input(events);
+        }
+
+        let polled = network.borrow_mut().poll();
+        match polled {
+            Ok(events) => for event in events {
+                match event {
+                    NetworkEvent::Message(Message::Notice(text)) => {
+                        println!("Server: {}", text);
+                    }
+                    NetworkEvent::Message(Message::Kicked(reason)) => {
+                        println!("Kicked by the server: {}", reason);
+                    }
+                    NetworkEvent::Message(msg) => {
+                        active_scene_context.borrow_mut().network_input(msg);
+                    }
+                    NetworkEvent::StateChanged(state) => {
+                        println!("Connection {:?}", state);
+                        active_scene_context.borrow_mut().connection_changed(state);
+                    }
+                }
+            },
+            Err(e) => println!("Network error: {:?}", e),
+        }
+
+        let due = clock.borrow_mut().take_due();
+        for callback in due {
+            callback();
+        }
+        let now = clock.borrow().elapsed();
+        for _ in 0..sim.advance(now) {
+            active_scene_context.borrow_mut().update(sim.step());
+        }
+        active_scene_context.borrow().render(sim.alpha());
 
-        active_scene_context.borrow_mut().update();
-        active_scene_context.borrow_mut().render();
+        if let Some(ref path) = config.screenshot {
+            if !save_screenshot(&renderer, &window, path) {
+                exit_code = 1;
+            }
+            break;
+        }
+        if let Some(path) = screenshot {
+            save_screenshot(&renderer, &window, &path);
+        }
 
         window.gl_swap_window();
 
+        if let Some(frame_cap) = frame_cap {
+            let spent = frame_start.elapsed();
+            if spent < frame_cap {
+                thread::sleep(frame_cap - spent);
+            }
+        }
+
+        frames.frame();
+        if last_report.elapsed() >= Duration::from_secs(1) {
+            last_report = Instant::now();
+            let title = format!(
+                "Title ({:.0} fps, worst frame {:.1} ms)",
+                frames.fps(),
+                timers::as_millis_f32(frames.longest())
+            );
+            window.set_title(&title).unwrap();
+        }
+
         let context = active_scene_context.borrow().switch_context();
         if context.is_some() {
+            // Replacing the scene drops the old one and its GPU resources.
             active_scene_context = context.unwrap();
+            if cfg!(debug_assertions) {
+                println!("Live GPU resources: {:?}", renderer.borrow().live());
+            }
+        }
+    }
+
+    // The scene goes first: a room scene still gets to leave its room, and
+    // the GL context must outlive its buffers and programs.
+    drop(active_scene_context);
+    if let Some(framebuffer) = screenshot_target {
+        renderer.borrow_mut().delete_framebuffer(framebuffer);
+    }
+    if cfg!(debug_assertions) {
+        let live = renderer.borrow().live();
+        if live.total() > 0 {
+            println!("Leaked GPU resources: {:?}", live);
         }
     }
 
-    // Disonnect from the server
-    let msg = MessageType::RemoveFromListeners;
-    let buf = protocol::pack(&msg);
-    network_source.send_to(&buf, "127.0.0.1:45000").unwrap();
+    if exit_code != 0 {
+        drop(network);
+        std::process::exit(exit_code);
+    }
+
+    // Leaving scope drops `network`, which disconnects from the server.
 }
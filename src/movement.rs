@@ -0,0 +1,118 @@
+// Member movement rules. The server simulates with them and the client
+// predicts with them, so both must agree to the bit for reconciliation to
+// converge.
+
+use cgmath::{InnerSpace, Point2, Vector2};
+use network::message::Input;
+use std::time::Instant;
+use timers;
+
+// One unit per millisecond.
+pub const MEMBER_SPEED: f32 = 1.0;
+// Longest single input the server accepts; anything longer is cut short.
+// `InputBudget` bounds the total.
+pub const MAX_INPUT_MS: u16 = 100;
+// Input time a member can bank while standing still. It covers inputs that
+// the network delivers in bunches, but is too little to sprint on.
+pub const MAX_INPUT_BANK_MS: f32 = 250.0;
+
+// Clients send a unit vector, but the server is the authority on speed, so
+// never trust the magnitude.
+pub fn direction(dx: f32, dy: f32) -> Option<Vector2<f32>> {
+    let direction = Vector2::new(dx, dy);
+    if direction.x.is_finite() && direction.y.is_finite() && direction.magnitude2() > 0f32 {
+        Some(direction.normalize())
+    } else {
+        None
+    }
+}
+
+pub fn step(pos: Point2<f32>, direction: Vector2<f32>, ms: f32) -> Point2<f32> {
+    pos + direction * MEMBER_SPEED * ms
+}
+
+pub fn apply(pos: Point2<f32>, input: &Input) -> Point2<f32> {
+    match direction(input.direction.x, input.direction.y) {
+        Some(direction) => {
+            let ms = ::std::cmp::min(input.duration_ms, MAX_INPUT_MS);
+            step(pos, direction, ms as f32)
+        }
+        None => pos,
+    }
+}
+
+// Sequence numbers wrap, so "newer" means less than half the range ahead.
+pub fn is_newer(seq: u16, than: u16) -> bool {
+    let ahead = seq.wrapping_sub(than);
+    ahead != 0 && ahead < 0x8000
+}
+
+// Movement time the server grants one member. It accrues with the wall
+// clock and every input spends its duration, so a client can't cover
+// ground faster by sending more inputs or claiming longer ones.
+pub struct InputBudget {
+    available_ms: f32,
+    last: Instant,
+}
+
+impl InputBudget {
+    pub fn new(now: Instant) -> InputBudget {
+        InputBudget {
+            available_ms: 0f32,
+            last: now,
+        }
+    }
+
+    // How much of an input lasting `requested_ms` may be applied at `now`.
+    pub fn grant(&mut self, requested_ms: u16, now: Instant) -> u16 {
+        if now > self.last {
+            let passed = timers::as_millis_f32(now.duration_since(self.last));
+            self.available_ms = (self.available_ms + passed).min(MAX_INPUT_BANK_MS);
+            self.last = now;
+        }
+        let granted = (requested_ms as f32).min(self.available_ms.floor());
+        self.available_ms -= granted;
+        granted as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn budget_follows_the_wall_clock() {
+        let start = Instant::now();
+        let mut budget = InputBudget::new(start);
+        assert_eq!(budget.grant(16, start), 0);
+
+        let later = start + Duration::from_millis(50);
+        assert_eq!(budget.grant(16, later), 16);
+        assert_eq!(budget.grant(16, later), 16);
+        assert_eq!(budget.grant(16, later), 16);
+        // Only 2 ms of the 50 are left.
+        assert_eq!(budget.grant(16, later), 2);
+        assert_eq!(budget.grant(16, later), 0);
+    }
+
+    #[test]
+    fn budget_banks_at_most_the_limit() {
+        let start = Instant::now();
+        let mut budget = InputBudget::new(start);
+        let later = start + Duration::from_secs(10);
+        let mut granted = 0u32;
+        for _ in 0..100 {
+            granted += budget.grant(MAX_INPUT_MS, later) as u32;
+        }
+        assert_eq!(granted, MAX_INPUT_BANK_MS as u32);
+    }
+
+    #[test]
+    fn sequence_numbers_wrap() {
+        assert!(is_newer(1, 0));
+        assert!(is_newer(0, u16::MAX));
+        assert!(!is_newer(0, 0));
+        assert!(!is_newer(u16::MAX, 0));
+    }
+}
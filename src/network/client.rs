@@ -0,0 +1,173 @@
+use config::Config;
+use network::connection::ConnectionState;
+use network::message::Message;
+use network::worker::{Command, Event, Worker};
+use protocol;
+use protocol::enums::MessageType;
+use std::cell::RefCell;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::rc::Rc;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread;
+
+pub type RefNetworkClient = Rc<RefCell<dyn Network>>;
+
+pub enum NetworkEvent {
+    Message(Message),
+    StateChanged(ConnectionState),
+}
+
+// What the render loop and the scenes need from the connection to the
+// server. `NetworkClient` is the real one; `OfflineNetwork` stands in for
+// screenshot runs and tests.
+pub trait Network {
+    // Fire-and-forget; fine for traffic that is superseded by the next
+    // message anyway, such as `Input`.
+    fn send(&mut self, msg: Message) -> io::Result<()>;
+    // Delivered in order with the other reliable messages. Anything still
+    // unacknowledged when the session drops is sent again on the next one,
+    // so a message the server got just before a reconnect may arrive twice.
+    fn send_reliable(&mut self, msg: Message) -> io::Result<()>;
+    // Every message and connection state change since the last call, in
+    // order.
+    fn poll(&mut self) -> io::Result<Vec<NetworkEvent>>;
+}
+
+// Handle to the network thread. The socket lives on the worker; the render
+// loop only exchanges decoded messages with it over channels.
+pub struct NetworkClient {
+    commands: Sender<Command>,
+    events: Receiver<Event>,
+    worker: Option<thread::JoinHandle<()>>,
+    pending_error: Option<io::Error>,
+    guard: SessionGuard,
+}
+
+// Last line of defence for the disconnect message. Normally the worker says
+// goodbye on shutdown; if it died instead, the guard sends a bare
+// `RemoveFromListeners` from its own handle on the socket.
+struct SessionGuard {
+    socket: UdpSocket,
+    server_addr: SocketAddr,
+}
+
+impl SessionGuard {
+    fn disconnect(&self) {
+        let buf = protocol::pack(&MessageType::RemoveFromListeners);
+        let _ = self.socket.send_to(&buf, self.server_addr);
+    }
+}
+
+impl NetworkClient {
+    pub fn open(config: &Config) -> io::Result<NetworkClient> {
+        let socket = match UdpSocket::bind(config.bind_addr) {
+            Ok(socket) => socket,
+            Err(ref e) if config.ephemeral_fallback && e.kind() == io::ErrorKind::AddrInUse => {
+                let mut addr = config.bind_addr;
+                addr.set_port(0);
+                UdpSocket::bind(addr)?
+            }
+            Err(e) => return Err(e),
+        };
+
+        println!(
+            "Listening on {:?}, server {:?}",
+            socket.local_addr()?,
+            config.server_addr
+        );
+
+        let guard = SessionGuard {
+            socket: socket.try_clone()?,
+            server_addr: config.server_addr,
+        };
+
+        let (command_sender, command_receiver) = channel::<Command>();
+        let (event_sender, event_receiver) = channel::<Event>();
+        let worker = Worker::new(socket, config.server_addr, command_receiver, event_sender)?;
+
+        let handle = thread::Builder::new()
+            .name("network".to_string())
+            .spawn(move || worker.run())?;
+
+        Ok(NetworkClient {
+            commands: command_sender,
+            events: event_receiver,
+            worker: Some(handle),
+            pending_error: None,
+            guard: guard,
+        })
+    }
+
+    pub fn shared(self) -> RefNetworkClient {
+        Rc::new(RefCell::new(self))
+    }
+}
+
+impl Network for NetworkClient {
+    fn send(&mut self, msg: Message) -> io::Result<()> {
+        self.commands
+            .send(Command::Send(msg))
+            .map_err(|_| worker_stopped())
+    }
+
+    // Retransmitted until the server acknowledges it.
+    fn send_reliable(&mut self, msg: Message) -> io::Result<()> {
+        self.commands
+            .send(Command::SendReliable(msg))
+            .map_err(|_| worker_stopped())
+    }
+
+    // A socket error is reported once the events that came before it have
+    // been handed out.
+    fn poll(&mut self) -> io::Result<Vec<NetworkEvent>> {
+        if let Some(e) = self.pending_error.take() {
+            return Err(e);
+        }
+
+        let mut events = Vec::new();
+        loop {
+            match self.events.try_recv() {
+                Ok(Event::Message(msg)) => events.push(NetworkEvent::Message(msg)),
+                Ok(Event::State(state)) => events.push(NetworkEvent::StateChanged(state)),
+                Ok(Event::Error(e)) => {
+                    if events.is_empty() {
+                        return Err(e);
+                    }
+                    self.pending_error = Some(e);
+                    break;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if events.is_empty() {
+                        return Err(worker_stopped());
+                    }
+                    break;
+                }
+            }
+        }
+        Ok(events)
+    }
+}
+
+// Runs on every way out of `main`: a normal return, a window close, SIGINT or
+// SIGTERM (SDL turns both into `Event::Quit`) and a panic unwinding the
+// render thread.
+impl Drop for NetworkClient {
+    fn drop(&mut self) {
+        // Commands are handled in order, so anything sent before this point
+        // still goes out before the worker says goodbye and stops.
+        let stopped = self.commands.send(Command::Shutdown).is_ok();
+        let joined = match self.worker.take() {
+            Some(handle) => handle.join().is_ok(),
+            None => false,
+        };
+        if !stopped || !joined {
+            self.guard.disconnect();
+        }
+    }
+}
+
+fn worker_stopped() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "network thread stopped")
+}
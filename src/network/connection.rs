@@ -0,0 +1,268 @@
+use std::cmp;
+use std::time::{Duration, Instant};
+
+const CONNECT_RETRY_MS: u64 = 500;
+const CONNECT_TIMEOUT_MS: u64 = 3000;
+const HEARTBEAT_INTERVAL_MS: u64 = 1000;
+const SERVER_TIMEOUT_MS: u64 = 5000;
+const RECONNECT_DELAY_MS: u64 = 1000;
+const MAX_RECONNECT_DELAY_MS: u64 = 10000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionState {
+    Disconnected,
+    Connecting,
+    Connected,
+    TimedOut,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    SendConnect(u16),
+    SendHeartbeat,
+}
+
+// Tracks the client side of the connection lifecycle:
+//
+//   Disconnected -> Connecting -> Connected -> TimedOut -> Connecting ...
+//
+// Connecting repeats the handshake until the server accepts or the attempt
+// times out. Connected sends heartbeats and times out when the server has
+// been silent too long. TimedOut waits with exponential backoff before the
+// next attempt.
+pub struct ConnectionMonitor {
+    state: ConnectionState,
+    since: Instant,
+    last_heard: Instant,
+    last_sent: Instant,
+    session: u16,
+    reconnect_delay: Duration,
+}
+
+impl ConnectionMonitor {
+    pub fn new(now: Instant, session: u16) -> ConnectionMonitor {
+        ConnectionMonitor {
+            state: ConnectionState::Disconnected,
+            since: now,
+            last_heard: now,
+            last_sent: now,
+            session: session,
+            reconnect_delay: Duration::from_millis(RECONNECT_DELAY_MS),
+        }
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    pub fn connect(&mut self, now: Instant) -> Action {
+        self.session = self.session.wrapping_add(1);
+        self.last_sent = now;
+        self.enter(ConnectionState::Connecting, now);
+        Action::SendConnect(self.session)
+    }
+
+    pub fn disconnect(&mut self, now: Instant) {
+        self.enter(ConnectionState::Disconnected, now);
+    }
+
+    // The session broke down although the server may still be answering,
+    // as when a reliable message was never acknowledged. It is abandoned
+    // like a silent one and a new session follows after the usual delay.
+    pub fn failed(&mut self, now: Instant) {
+        if self.state == ConnectionState::Connected {
+            self.enter(ConnectionState::TimedOut, now);
+        }
+    }
+
+    // Any datagram from the server proves it is alive.
+    pub fn heard(&mut self, now: Instant) {
+        self.last_heard = now;
+    }
+
+    // Returns true when the accept completes the current handshake.
+    pub fn accepted(&mut self, session: u16, now: Instant) -> bool {
+        self.heard(now);
+        if self.state != ConnectionState::Connecting || session != self.session {
+            return false;
+        }
+        self.last_sent = now;
+        self.reconnect_delay = Duration::from_millis(RECONNECT_DELAY_MS);
+        self.enter(ConnectionState::Connected, now);
+        true
+    }
+
+    pub fn tick(&mut self, now: Instant) -> Option<Action> {
+        match self.state {
+            ConnectionState::Disconnected => None,
+
+            ConnectionState::Connecting => {
+                if now.duration_since(self.since) >= Duration::from_millis(CONNECT_TIMEOUT_MS) {
+                    self.enter(ConnectionState::TimedOut, now);
+                    None
+                } else if now.duration_since(self.last_sent)
+                    >= Duration::from_millis(CONNECT_RETRY_MS)
+                {
+                    self.last_sent = now;
+                    Some(Action::SendConnect(self.session))
+                } else {
+                    None
+                }
+            }
+
+            ConnectionState::Connected => {
+                if now.duration_since(self.last_heard) >= Duration::from_millis(SERVER_TIMEOUT_MS) {
+                    self.enter(ConnectionState::TimedOut, now);
+                    None
+                } else if now.duration_since(self.last_sent)
+                    >= Duration::from_millis(HEARTBEAT_INTERVAL_MS)
+                {
+                    self.last_sent = now;
+                    Some(Action::SendHeartbeat)
+                } else {
+                    None
+                }
+            }
+
+            ConnectionState::TimedOut => {
+                if now.duration_since(self.since) >= self.reconnect_delay {
+                    self.reconnect_delay = cmp::min(
+                        self.reconnect_delay * 2,
+                        Duration::from_millis(MAX_RECONNECT_DELAY_MS),
+                    );
+                    Some(self.connect(now))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    fn enter(&mut self, state: ConnectionState, now: Instant) {
+        self.state = state;
+        self.since = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn connected(start: Instant) -> ConnectionMonitor {
+        let mut monitor = ConnectionMonitor::new(start, 0);
+        monitor.connect(start);
+        assert!(monitor.accepted(1, start));
+        monitor
+    }
+
+    #[test]
+    fn handshake_moves_to_connected() {
+        let start = Instant::now();
+        let mut monitor = ConnectionMonitor::new(start, 0);
+        assert_eq!(monitor.state(), ConnectionState::Disconnected);
+        assert_eq!(monitor.tick(start + ms(60000)), None);
+
+        assert_eq!(monitor.connect(start), Action::SendConnect(1));
+        assert_eq!(monitor.state(), ConnectionState::Connecting);
+        // An accept for some other session changes nothing.
+        assert!(!monitor.accepted(0, start));
+        assert_eq!(monitor.state(), ConnectionState::Connecting);
+
+        assert!(monitor.accepted(1, start + ms(10)));
+        assert_eq!(monitor.state(), ConnectionState::Connected);
+        // A repeated accept does not complete it twice.
+        assert!(!monitor.accepted(1, start + ms(20)));
+    }
+
+    #[test]
+    fn connect_is_repeated_until_the_attempt_times_out() {
+        let start = Instant::now();
+        let mut monitor = ConnectionMonitor::new(start, 0);
+        monitor.connect(start);
+
+        assert_eq!(monitor.tick(start + ms(CONNECT_RETRY_MS - 1)), None);
+        assert_eq!(
+            monitor.tick(start + ms(CONNECT_RETRY_MS)),
+            Some(Action::SendConnect(1))
+        );
+        assert_eq!(monitor.tick(start + ms(CONNECT_TIMEOUT_MS)), None);
+        assert_eq!(monitor.state(), ConnectionState::TimedOut);
+    }
+
+    #[test]
+    fn heartbeats_keep_going_while_the_server_answers() {
+        let start = Instant::now();
+        let mut monitor = connected(start);
+
+        assert_eq!(monitor.tick(start + ms(HEARTBEAT_INTERVAL_MS - 1)), None);
+        let beat = start + ms(HEARTBEAT_INTERVAL_MS);
+        assert_eq!(monitor.tick(beat), Some(Action::SendHeartbeat));
+        assert_eq!(monitor.tick(beat), None);
+
+        monitor.heard(start + ms(SERVER_TIMEOUT_MS - 1));
+        monitor.tick(start + ms(SERVER_TIMEOUT_MS));
+        assert_eq!(monitor.state(), ConnectionState::Connected);
+    }
+
+    #[test]
+    fn silence_times_out() {
+        let start = Instant::now();
+        let mut monitor = connected(start);
+        assert_eq!(monitor.tick(start + ms(SERVER_TIMEOUT_MS)), None);
+        assert_eq!(monitor.state(), ConnectionState::TimedOut);
+    }
+
+    #[test]
+    fn a_failed_session_is_replaced_with_a_new_one() {
+        let start = Instant::now();
+        let mut monitor = connected(start);
+        monitor.failed(start);
+        assert_eq!(monitor.state(), ConnectionState::TimedOut);
+
+        let retry = start + ms(RECONNECT_DELAY_MS);
+        assert_eq!(monitor.tick(retry), Some(Action::SendConnect(2)));
+        assert_eq!(monitor.state(), ConnectionState::Connecting);
+        // Only a connected session can fail.
+        monitor.failed(retry);
+        assert_eq!(monitor.state(), ConnectionState::Connecting);
+    }
+
+    #[test]
+    fn reconnects_back_off_until_one_succeeds() {
+        let start = Instant::now();
+        let mut monitor = ConnectionMonitor::new(start, 0);
+        monitor.connect(start);
+        let mut now = start + ms(CONNECT_TIMEOUT_MS);
+        monitor.tick(now);
+
+        let mut delays = Vec::new();
+        for _ in 0..6 {
+            let timed_out = now;
+            while monitor.state() == ConnectionState::TimedOut {
+                now += ms(100);
+                monitor.tick(now);
+            }
+            delays.push((now - timed_out).as_millis());
+            now += ms(CONNECT_TIMEOUT_MS);
+            monitor.tick(now);
+        }
+        assert_eq!(delays, vec![1000, 2000, 4000, 8000, 10000, 10000]);
+
+        // Success resets the delay.
+        now += ms(MAX_RECONNECT_DELAY_MS);
+        let session = match monitor.tick(now) {
+            Some(Action::SendConnect(session)) => session,
+            action => panic!("expected a connect, got {:?}", action),
+        };
+        assert!(monitor.accepted(session, now));
+        monitor.failed(now);
+        assert_eq!(
+            monitor.tick(now + ms(RECONNECT_DELAY_MS)),
+            Some(Action::SendConnect(session + 1))
+        );
+    }
+}
@@ -0,0 +1,388 @@
+use cgmath::{Point2, Vector2};
+use network::wire::{self, Reader, FRAME_MARKER, KIND_MESSAGE};
+use protocol;
+use protocol::enums::MessageType;
+
+const TAG_LEAVE_ROOM: u8 = 1;
+const TAG_NOTICE: u8 = 2;
+const TAG_KICKED: u8 = 3;
+const TAG_ROOM_LIST: u8 = 4;
+const TAG_ROOM_ADDED: u8 = 5;
+const TAG_ROOM_REMOVED: u8 = 6;
+const TAG_ROOM_UPDATED: u8 = 7;
+const TAG_LIST_ROOMS: u8 = 8;
+const TAG_JOIN_ROOM: u8 = 9;
+const TAG_ROOM_JOINED: u8 = 10;
+const TAG_JOIN_REFUSED: u8 = 11;
+const TAG_MEMBER_JOINED: u8 = 12;
+const TAG_MEMBER_LEFT: u8 = 13;
+const TAG_ROOM_STATE: u8 = 14;
+const TAG_INPUT: u8 = 15;
+const TAG_INPUT_ACK: u8 = 16;
+
+// What the lobby needs to know about a room.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoomInfo {
+    pub id: u16,
+    pub name: String,
+    pub capacity: u16,
+    pub members: u16,
+}
+
+impl RoomInfo {
+    pub fn is_full(&self) -> bool {
+        self.members >= self.capacity
+    }
+
+    fn write(&self, buf: &mut Vec<u8>) {
+        wire::put_u16(buf, self.id);
+        wire::put_str(buf, &self.name);
+        wire::put_u16(buf, self.capacity);
+        wire::put_u16(buf, self.members);
+    }
+
+    fn read(reader: &mut Reader) -> Option<RoomInfo> {
+        Some(RoomInfo {
+            id: reader.u16()?,
+            name: reader.string()?,
+            capacity: reader.u16()?,
+            members: reader.u16()?,
+        })
+    }
+}
+
+// Where a room member is, as of the server's last tick.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberState {
+    pub id: u16,
+    pub pos: Point2<f32>,
+}
+
+impl MemberState {
+    fn write(&self, buf: &mut Vec<u8>) {
+        wire::put_u16(buf, self.id);
+        wire::put_f32(buf, self.pos.x);
+        wire::put_f32(buf, self.pos.y);
+    }
+
+    fn read(reader: &mut Reader) -> Option<MemberState> {
+        Some(MemberState {
+            id: reader.u16()?,
+            pos: Point2::new(reader.f32()?, reader.f32()?),
+        })
+    }
+}
+
+// One frame of local movement: head along `direction` for `duration_ms`.
+// A zero direction stands still.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Input {
+    pub seq: u16,
+    pub direction: Vector2<f32>,
+    pub duration_ms: u16,
+}
+
+// Everything the client and server exchange. `chunk_protocol` messages are
+// carried as they are; the rest are extensions this crate needs before the
+// protocol crate grows equivalents.
+pub enum Message {
+    Protocol(MessageType),
+    LeaveRoom,
+    // Free text from the server operator.
+    Notice(String),
+    // The server dropped this client; it should not reconnect on its own.
+    Kicked(String),
+    // The room catalogue. The server sends every open room, in id order,
+    // when a client starts listening or asks with `ListRooms`, then keeps it
+    // current with the three events below. All of them travel reliably, so
+    // they arrive in order.
+    RoomList(Vec<RoomInfo>),
+    RoomAdded(RoomInfo),
+    RoomRemoved(u16),
+    RoomUpdated(RoomInfo),
+    ListRooms,
+    // Asks for a place in the room with this id. Supersedes the bare
+    // `MemberIn`, which the server still honours with the first open room.
+    JoinRoom(u16),
+    // Answers `JoinRoom`. `member` is the joiner's own id among `members`.
+    RoomJoined {
+        room: RoomInfo,
+        member: u16,
+        members: Vec<u16>,
+    },
+    JoinRefused(u16, String),
+    // Keep the membership list of the room the client is in current.
+    MemberJoined(u16),
+    MemberLeft(u16),
+    // Every member of the room the client is in, sent unreliably each tick.
+    RoomState(Vec<MemberState>),
+    // Movement for the client's own member, sent unreliably every frame it
+    // moves. Supersedes `MemberMove` and `MemberStopMove`.
+    Input(Input),
+    // The last input the server applied and where it left the member, sent
+    // to each member every tick so the client can reconcile its prediction.
+    InputAck {
+        seq: u16,
+        pos: Point2<f32>,
+    },
+}
+
+impl From<MessageType> for Message {
+    fn from(msg: MessageType) -> Message {
+        Message::Protocol(msg)
+    }
+}
+
+impl Message {
+    pub fn encode(&self) -> Vec<u8> {
+        match *self {
+            Message::Protocol(ref msg) => protocol::pack(msg),
+            Message::LeaveRoom => header(TAG_LEAVE_ROOM),
+            Message::Notice(ref text) => {
+                let mut buf = header(TAG_NOTICE);
+                wire::put_str(&mut buf, text);
+                buf
+            }
+            Message::Kicked(ref reason) => {
+                let mut buf = header(TAG_KICKED);
+                wire::put_str(&mut buf, reason);
+                buf
+            }
+            Message::RoomList(ref rooms) => {
+                let mut buf = header(TAG_ROOM_LIST);
+                wire::put_u16(&mut buf, rooms.len() as u16);
+                for room in rooms.iter() {
+                    room.write(&mut buf);
+                }
+                buf
+            }
+            Message::RoomAdded(ref room) => {
+                let mut buf = header(TAG_ROOM_ADDED);
+                room.write(&mut buf);
+                buf
+            }
+            Message::RoomRemoved(id) => {
+                let mut buf = header(TAG_ROOM_REMOVED);
+                wire::put_u16(&mut buf, id);
+                buf
+            }
+            Message::RoomUpdated(ref room) => {
+                let mut buf = header(TAG_ROOM_UPDATED);
+                room.write(&mut buf);
+                buf
+            }
+            Message::ListRooms => header(TAG_LIST_ROOMS),
+            Message::JoinRoom(id) => {
+                let mut buf = header(TAG_JOIN_ROOM);
+                wire::put_u16(&mut buf, id);
+                buf
+            }
+            Message::RoomJoined {
+                ref room,
+                member,
+                ref members,
+            } => {
+                let mut buf = header(TAG_ROOM_JOINED);
+                room.write(&mut buf);
+                wire::put_u16(&mut buf, member);
+                wire::put_u16(&mut buf, members.len() as u16);
+                for id in members.iter() {
+                    wire::put_u16(&mut buf, *id);
+                }
+                buf
+            }
+            Message::JoinRefused(id, ref reason) => {
+                let mut buf = header(TAG_JOIN_REFUSED);
+                wire::put_u16(&mut buf, id);
+                wire::put_str(&mut buf, reason);
+                buf
+            }
+            Message::MemberJoined(id) => {
+                let mut buf = header(TAG_MEMBER_JOINED);
+                wire::put_u16(&mut buf, id);
+                buf
+            }
+            Message::MemberLeft(id) => {
+                let mut buf = header(TAG_MEMBER_LEFT);
+                wire::put_u16(&mut buf, id);
+                buf
+            }
+            Message::Input(ref input) => {
+                let mut buf = header(TAG_INPUT);
+                wire::put_u16(&mut buf, input.seq);
+                wire::put_f32(&mut buf, input.direction.x);
+                wire::put_f32(&mut buf, input.direction.y);
+                wire::put_u16(&mut buf, input.duration_ms);
+                buf
+            }
+            Message::InputAck { seq, pos } => {
+                let mut buf = header(TAG_INPUT_ACK);
+                wire::put_u16(&mut buf, seq);
+                wire::put_f32(&mut buf, pos.x);
+                wire::put_f32(&mut buf, pos.y);
+                buf
+            }
+            Message::RoomState(ref members) => {
+                let mut buf = header(TAG_ROOM_STATE);
+                wire::put_u16(&mut buf, members.len() as u16);
+                for member in members.iter() {
+                    member.write(&mut buf);
+                }
+                buf
+            }
+        }
+    }
+
+    pub fn decode(payload: &Vec<u8>) -> Option<Message> {
+        if payload.first() != Some(&FRAME_MARKER) {
+            return Some(Message::Protocol(protocol::unpack(payload)));
+        }
+
+        let mut reader = Reader::new(payload);
+        reader.u8()?;
+        if reader.u8()? != KIND_MESSAGE {
+            return None;
+        }
+        match reader.u8()? {
+            TAG_LEAVE_ROOM => Some(Message::LeaveRoom),
+            TAG_NOTICE => Some(Message::Notice(reader.string()?)),
+            TAG_KICKED => Some(Message::Kicked(reader.string()?)),
+            TAG_ROOM_LIST => {
+                let count = reader.u16()?;
+                let mut rooms = Vec::new();
+                for _ in 0..count {
+                    rooms.push(RoomInfo::read(&mut reader)?);
+                }
+                Some(Message::RoomList(rooms))
+            }
+            TAG_ROOM_ADDED => Some(Message::RoomAdded(RoomInfo::read(&mut reader)?)),
+            TAG_ROOM_REMOVED => Some(Message::RoomRemoved(reader.u16()?)),
+            TAG_ROOM_UPDATED => Some(Message::RoomUpdated(RoomInfo::read(&mut reader)?)),
+            TAG_LIST_ROOMS => Some(Message::ListRooms),
+            TAG_JOIN_ROOM => Some(Message::JoinRoom(reader.u16()?)),
+            TAG_ROOM_JOINED => {
+                let room = RoomInfo::read(&mut reader)?;
+                let member = reader.u16()?;
+                let count = reader.u16()?;
+                let mut members = Vec::new();
+                for _ in 0..count {
+                    members.push(reader.u16()?);
+                }
+                Some(Message::RoomJoined {
+                    room: room,
+                    member: member,
+                    members: members,
+                })
+            }
+            TAG_JOIN_REFUSED => Some(Message::JoinRefused(reader.u16()?, reader.string()?)),
+            TAG_MEMBER_JOINED => Some(Message::MemberJoined(reader.u16()?)),
+            TAG_MEMBER_LEFT => Some(Message::MemberLeft(reader.u16()?)),
+            TAG_INPUT => Some(Message::Input(Input {
+                seq: reader.u16()?,
+                direction: Vector2::new(reader.f32()?, reader.f32()?),
+                duration_ms: reader.u16()?,
+            })),
+            TAG_INPUT_ACK => Some(Message::InputAck {
+                seq: reader.u16()?,
+                pos: Point2::new(reader.f32()?, reader.f32()?),
+            }),
+            TAG_ROOM_STATE => {
+                let count = reader.u16()?;
+                let mut members = Vec::new();
+                for _ in 0..count {
+                    members.push(MemberState::read(&mut reader)?);
+                }
+                Some(Message::RoomState(members))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn header(tag: u8) -> Vec<u8> {
+    let mut buf = vec![FRAME_MARKER, KIND_MESSAGE];
+    buf.push(tag);
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn room(id: u16) -> RoomInfo {
+        RoomInfo {
+            id: id,
+            name: format!("Room {}", id),
+            capacity: 8,
+            members: 3,
+        }
+    }
+
+    // `Message` can not be compared directly, since `MessageType` does not
+    // implement `PartialEq`, so compare what each decodes back to.
+    fn round_trip(msg: Message) {
+        let encoded = msg.encode();
+        let decoded = Message::decode(&encoded).expect("message did not decode");
+        assert_eq!(decoded.encode(), encoded);
+    }
+
+    #[test]
+    fn extensions_round_trip() {
+        round_trip(Message::LeaveRoom);
+        round_trip(Message::Notice("Restarting soon".to_string()));
+        round_trip(Message::Kicked("Bye".to_string()));
+        round_trip(Message::RoomList(vec![room(1), room(2)]));
+        round_trip(Message::RoomAdded(room(3)));
+        round_trip(Message::RoomRemoved(3));
+        round_trip(Message::RoomUpdated(room(1)));
+        round_trip(Message::ListRooms);
+        round_trip(Message::JoinRoom(2));
+        round_trip(Message::RoomJoined {
+            room: room(2),
+            member: 5,
+            members: vec![4, 5],
+        });
+        round_trip(Message::JoinRefused(2, "Room is full".to_string()));
+        round_trip(Message::MemberJoined(6));
+        round_trip(Message::MemberLeft(6));
+        round_trip(Message::RoomState(vec![MemberState {
+            id: 4,
+            pos: Point2::new(1.5, -2.0),
+        }]));
+        round_trip(Message::Input(Input {
+            seq: u16::MAX,
+            direction: Vector2::new(0.0, 1.0),
+            duration_ms: 16,
+        }));
+        round_trip(Message::InputAck {
+            seq: 9,
+            pos: Point2::new(-3.0, 4.25),
+        });
+    }
+
+    #[test]
+    fn decoded_fields_match() {
+        match Message::decode(&Message::RoomList(vec![room(1), room(2)]).encode()) {
+            Some(Message::RoomList(rooms)) => assert_eq!(rooms, vec![room(1), room(2)]),
+            _ => panic!("expected a room list"),
+        }
+        let input = Input {
+            seq: 3,
+            direction: Vector2::new(-1.0, 0.0),
+            duration_ms: 20,
+        };
+        match Message::decode(&Message::Input(input.clone()).encode()) {
+            Some(Message::Input(decoded)) => assert_eq!(decoded, input),
+            _ => panic!("expected an input"),
+        }
+    }
+
+    #[test]
+    fn truncated_and_unknown_extensions_are_dropped() {
+        let mut encoded = Message::RoomAdded(room(1)).encode();
+        encoded.pop();
+        assert!(Message::decode(&encoded).is_none());
+        assert!(Message::decode(&vec![FRAME_MARKER, KIND_MESSAGE, 0xEE]).is_none());
+        assert!(Message::decode(&vec![FRAME_MARKER, 1, TAG_LEAVE_ROOM]).is_none());
+    }
+}
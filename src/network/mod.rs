@@ -0,0 +1,21 @@
+pub use self::client::*;
+pub use self::connection::ConnectionState;
+pub use self::message::{Input, MemberState, Message, RoomInfo};
+pub use self::offline::OfflineNetwork;
+
+mod client;
+mod connection;
+pub mod message;
+mod offline;
+pub mod reliable;
+pub mod wire;
+mod worker;
+
+// Largest UDP payload over IPv4. A datagram that fills the whole buffer may
+// have been cut short by the kernel, so both ends drop it instead of
+// decoding it.
+pub const MAX_DATAGRAM_SIZE: usize = 65507;
+
+// How long a blocking read may wait before the caller gets on with its other
+// work: the client worker its outgoing messages, the server its ticks.
+pub const RECV_TIMEOUT_MS: u64 = 5;
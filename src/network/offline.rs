@@ -0,0 +1,44 @@
+use network::client::{Network, NetworkEvent};
+use network::message::Message;
+use std::io;
+
+// A server that is never reached. Whatever is queued with `deliver` comes
+// out of the next `poll`, and everything sent is kept, with whether it was
+// sent reliably, so fixtures and tests can script both directions.
+pub struct OfflineNetwork {
+    incoming: Vec<NetworkEvent>,
+    sent: Vec<(Message, bool)>,
+}
+
+impl OfflineNetwork {
+    pub fn new() -> OfflineNetwork {
+        OfflineNetwork {
+            incoming: Vec::new(),
+            sent: Vec::new(),
+        }
+    }
+
+    pub fn deliver(&mut self, event: NetworkEvent) {
+        self.incoming.push(event);
+    }
+
+    pub fn sent(&self) -> &[(Message, bool)] {
+        &self.sent
+    }
+}
+
+impl Network for OfflineNetwork {
+    fn send(&mut self, msg: Message) -> io::Result<()> {
+        self.sent.push((msg, false));
+        Ok(())
+    }
+
+    fn send_reliable(&mut self, msg: Message) -> io::Result<()> {
+        self.sent.push((msg, true));
+        Ok(())
+    }
+
+    fn poll(&mut self) -> io::Result<Vec<NetworkEvent>> {
+        Ok(self.incoming.split_off(0))
+    }
+}
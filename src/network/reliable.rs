@@ -0,0 +1,262 @@
+use network::wire::Frame;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const INITIAL_TIMEOUT_MS: u64 = 100;
+const MAX_TIMEOUT_MS: u64 = 2000;
+const MAX_ATTEMPTS: u32 = 10;
+
+// Sequence numbers further ahead than this are treated as stale duplicates
+// from before a wrap rather than buffered.
+const RECEIVE_WINDOW: u16 = 1024;
+
+struct Pending {
+    payload: Vec<u8>,
+    last_sent: Instant,
+    timeout: Duration,
+    attempts: u32,
+}
+
+// One direction-pair of reliable, ordered traffic with a single peer.
+// Outgoing payloads are numbered and kept until acknowledged, resent with
+// exponential backoff. Incoming payloads are acknowledged, de-duplicated and
+// released strictly in sequence order.
+pub struct ReliableChannel {
+    next_seq: u16,
+    unacked: HashMap<u16, Pending>,
+    next_expected: u16,
+    out_of_order: HashMap<u16, Vec<u8>>,
+}
+
+impl ReliableChannel {
+    pub fn new() -> ReliableChannel {
+        ReliableChannel {
+            next_seq: 0,
+            unacked: HashMap::new(),
+            next_expected: 0,
+            out_of_order: HashMap::new(),
+        }
+    }
+
+    pub fn send(&mut self, payload: Vec<u8>, now: Instant) -> Frame {
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+
+        let frame = Frame::Reliable(seq, payload.clone());
+        self.unacked.insert(
+            seq,
+            Pending {
+                payload: payload,
+                last_sent: now,
+                timeout: Duration::from_millis(INITIAL_TIMEOUT_MS),
+                attempts: 1,
+            },
+        );
+        frame
+    }
+
+    pub fn ack(&mut self, seq: u16) {
+        self.unacked.remove(&seq);
+    }
+
+    // Returns the ack to send back and the payloads that are now deliverable.
+    // Duplicates are acknowledged again, since the first ack may have been
+    // lost, but never delivered twice.
+    pub fn receive(&mut self, seq: u16, payload: Vec<u8>) -> (Frame, Vec<Vec<u8>>) {
+        let mut delivered = Vec::new();
+        let ahead = seq.wrapping_sub(self.next_expected);
+
+        if ahead == 0 {
+            delivered.push(payload);
+            self.next_expected = self.next_expected.wrapping_add(1);
+            while let Some(next) = self.out_of_order.remove(&self.next_expected) {
+                delivered.push(next);
+                self.next_expected = self.next_expected.wrapping_add(1);
+            }
+        } else if ahead < RECEIVE_WINDOW {
+            self.out_of_order.entry(seq).or_insert(payload);
+        }
+
+        (Frame::Ack(seq), delivered)
+    }
+
+    // Returns the frames due for another attempt, or the sequence number of
+    // a message that ran out of attempts. The peer delivers strictly in
+    // order, so nothing sent after that message can ever reach it: the
+    // channel is broken and the caller must end the session.
+    pub fn retransmit(&mut self, now: Instant) -> Result<Vec<Frame>, u16> {
+        let mut frames = Vec::new();
+        let max_timeout = Duration::from_millis(MAX_TIMEOUT_MS);
+
+        for (seq, pending) in self.unacked.iter_mut() {
+            if now.duration_since(pending.last_sent) < pending.timeout {
+                continue;
+            }
+            if pending.attempts >= MAX_ATTEMPTS {
+                return Err(*seq);
+            }
+            pending.attempts += 1;
+            pending.last_sent = now;
+            pending.timeout = ::std::cmp::min(pending.timeout * 2, max_timeout);
+            frames.push(Frame::Reliable(*seq, pending.payload.clone()));
+        }
+
+        Ok(frames)
+    }
+
+    // Empties the channel and returns the payloads the peer never
+    // acknowledged, oldest first, so they can be sent again on a new session.
+    pub fn take_unacked(&mut self) -> Vec<Vec<u8>> {
+        let next_seq = self.next_seq;
+        let mut pending: Vec<(u16, Pending)> = self.unacked.drain().collect();
+        pending.sort_by_key(|&(seq, _)| seq.wrapping_sub(next_seq));
+        pending
+            .into_iter()
+            .map(|(_, pending)| pending.payload)
+            .collect()
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.unacked.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(seq: u16) -> Vec<u8> {
+        vec![(seq >> 8) as u8, seq as u8]
+    }
+
+    fn starting_at(seq: u16) -> ReliableChannel {
+        let mut channel = ReliableChannel::new();
+        channel.next_seq = seq;
+        channel.next_expected = seq;
+        channel
+    }
+
+    #[test]
+    fn in_order_payloads_are_delivered_and_acked() {
+        let mut channel = ReliableChannel::new();
+        assert_eq!(
+            channel.receive(0, payload(0)),
+            (Frame::Ack(0), vec![payload(0)])
+        );
+        assert_eq!(
+            channel.receive(1, payload(1)),
+            (Frame::Ack(1), vec![payload(1)])
+        );
+    }
+
+    #[test]
+    fn out_of_order_payloads_wait_for_the_gap() {
+        let mut channel = ReliableChannel::new();
+        assert_eq!(channel.receive(2, payload(2)), (Frame::Ack(2), vec![]));
+        assert_eq!(channel.receive(1, payload(1)), (Frame::Ack(1), vec![]));
+        assert_eq!(
+            channel.receive(0, payload(0)),
+            (Frame::Ack(0), vec![payload(0), payload(1), payload(2)])
+        );
+    }
+
+    #[test]
+    fn duplicates_are_acked_but_delivered_once() {
+        let mut channel = ReliableChannel::new();
+        channel.receive(0, payload(0));
+        assert_eq!(channel.receive(0, payload(0)), (Frame::Ack(0), vec![]));
+
+        // A duplicate of a buffered payload does not replace it either.
+        channel.receive(2, payload(2));
+        assert_eq!(channel.receive(2, vec![0xFF]), (Frame::Ack(2), vec![]));
+        assert_eq!(
+            channel.receive(1, payload(1)),
+            (Frame::Ack(1), vec![payload(1), payload(2)])
+        );
+    }
+
+    #[test]
+    fn sequence_numbers_wrap() {
+        let mut sender = starting_at(u16::MAX);
+        let mut receiver = starting_at(u16::MAX);
+        let now = Instant::now();
+
+        let last = sender.send(payload(1), now);
+        let first = sender.send(payload(2), now);
+        assert_eq!(last, Frame::Reliable(u16::MAX, payload(1)));
+        assert_eq!(first, Frame::Reliable(0, payload(2)));
+
+        // The wrapped one arrives first and is held back.
+        assert_eq!(receiver.receive(0, payload(2)), (Frame::Ack(0), vec![]));
+        assert_eq!(
+            receiver.receive(u16::MAX, payload(1)),
+            (Frame::Ack(u16::MAX), vec![payload(1), payload(2)])
+        );
+    }
+
+    #[test]
+    fn stale_sequence_numbers_are_not_buffered() {
+        let mut channel = starting_at(10);
+        // Far behind `next_expected`, so a late duplicate from before a wrap.
+        channel.receive(5, payload(5));
+        assert!(channel.out_of_order.is_empty());
+    }
+
+    #[test]
+    fn unacked_payloads_are_resent_with_backoff() {
+        let mut channel = ReliableChannel::new();
+        let start = Instant::now();
+        channel.send(payload(0), start);
+
+        assert_eq!(channel.retransmit(start), Ok(vec![]));
+        let first = start + Duration::from_millis(INITIAL_TIMEOUT_MS);
+        assert_eq!(
+            channel.retransmit(first),
+            Ok(vec![Frame::Reliable(0, payload(0))])
+        );
+        // The timeout doubled, so the old one is not enough any more.
+        let early = first + Duration::from_millis(INITIAL_TIMEOUT_MS);
+        assert_eq!(channel.retransmit(early), Ok(vec![]));
+        let second = first + Duration::from_millis(INITIAL_TIMEOUT_MS * 2);
+        assert_eq!(channel.retransmit(second).unwrap().len(), 1);
+
+        channel.ack(0);
+        assert!(channel.is_idle());
+        assert_eq!(
+            channel.retransmit(second + Duration::from_secs(60)),
+            Ok(vec![])
+        );
+    }
+
+    #[test]
+    fn running_out_of_attempts_breaks_the_channel() {
+        let mut channel = ReliableChannel::new();
+        let mut now = Instant::now();
+        channel.send(payload(0), now);
+
+        for _ in 1..MAX_ATTEMPTS {
+            now += Duration::from_millis(MAX_TIMEOUT_MS);
+            assert_eq!(channel.retransmit(now).unwrap().len(), 1);
+        }
+        now += Duration::from_millis(MAX_TIMEOUT_MS);
+        assert_eq!(channel.retransmit(now), Err(0));
+    }
+
+    #[test]
+    fn unacked_payloads_can_be_taken_in_send_order() {
+        let mut channel = starting_at(u16::MAX - 1);
+        let now = Instant::now();
+        for seq in 0..4 {
+            channel.send(payload(seq), now);
+        }
+        // Sent as MAX - 1, MAX, 0 and 1; the wrapped ones still come last.
+        channel.ack(u16::MAX);
+
+        assert_eq!(
+            channel.take_unacked(),
+            vec![payload(0), payload(2), payload(3)]
+        );
+        assert!(channel.is_idle());
+        assert_eq!(channel.take_unacked(), Vec::<Vec<u8>>::new());
+    }
+}
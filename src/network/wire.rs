@@ -0,0 +1,236 @@
+// Datagram framing shared by both ends of the connection.
+//
+// Plain `chunk_protocol` datagrams travel untouched, so unreliable traffic
+// such as `MemberMove` stays compatible with peers that know nothing about
+// framing. Anything else starts with `FRAME_MARKER`, a byte that
+// `chunk_protocol::pack` never puts first, followed by the frame kind and a
+// 16-bit argument. Messages this crate adds on top of `chunk_protocol` (see
+// `message.rs`) carry their own `KIND_MESSAGE` header instead and travel as
+// ordinary payloads, reliable or not.
+
+pub const FRAME_MARKER: u8 = 0xFF;
+
+const KIND_RELIABLE: u8 = 1;
+const KIND_ACK: u8 = 2;
+const KIND_CONNECT: u8 = 3;
+const KIND_ACCEPT: u8 = 4;
+const KIND_PING: u8 = 5;
+const KIND_PONG: u8 = 6;
+pub const KIND_MESSAGE: u8 = 7;
+
+#[derive(Debug, PartialEq)]
+pub enum Frame {
+    Unreliable(Vec<u8>),
+    Reliable(u16, Vec<u8>),
+    Ack(u16),
+    // Handshake. Both ends start a fresh reliable channel for a session id
+    // they have not seen before; repeats of the same id are answered again
+    // but change nothing.
+    Connect(u16),
+    Accept(u16),
+    // Heartbeat. The argument is echoed back unchanged.
+    Ping(u16),
+    Pong(u16),
+}
+
+impl Frame {
+    pub fn encode(&self) -> Vec<u8> {
+        match *self {
+            Frame::Unreliable(ref payload) => payload.clone(),
+            Frame::Reliable(seq, ref payload) => {
+                let mut buf = Vec::with_capacity(4 + payload.len());
+                buf.push(FRAME_MARKER);
+                buf.push(KIND_RELIABLE);
+                put_u16(&mut buf, seq);
+                buf.extend_from_slice(payload);
+                buf
+            }
+            Frame::Ack(seq) => header(KIND_ACK, seq),
+            Frame::Connect(session) => header(KIND_CONNECT, session),
+            Frame::Accept(session) => header(KIND_ACCEPT, session),
+            Frame::Ping(nonce) => header(KIND_PING, nonce),
+            Frame::Pong(nonce) => header(KIND_PONG, nonce),
+        }
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Frame> {
+        if buf.is_empty() {
+            return None;
+        }
+        if buf[0] != FRAME_MARKER || (buf.len() > 2 && buf[1] == KIND_MESSAGE) {
+            return Some(Frame::Unreliable(buf.to_vec()));
+        }
+        if buf.len() < 4 {
+            return None;
+        }
+
+        let arg = get_u16(&buf[2..4]);
+        match buf[1] {
+            KIND_RELIABLE if buf.len() > 4 => Some(Frame::Reliable(arg, buf[4..].to_vec())),
+            KIND_ACK => Some(Frame::Ack(arg)),
+            KIND_CONNECT => Some(Frame::Connect(arg)),
+            KIND_ACCEPT => Some(Frame::Accept(arg)),
+            KIND_PING => Some(Frame::Ping(arg)),
+            KIND_PONG => Some(Frame::Pong(arg)),
+            _ => None,
+        }
+    }
+}
+
+fn header(kind: u8, arg: u16) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(4);
+    buf.push(FRAME_MARKER);
+    buf.push(kind);
+    put_u16(&mut buf, arg);
+    buf
+}
+
+pub fn put_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.push((value >> 8) as u8);
+    buf.push(value as u8);
+}
+
+fn get_u16(buf: &[u8]) -> u16 {
+    (buf[0] as u16) << 8 | buf[1] as u16
+}
+
+pub fn put_f32(buf: &mut Vec<u8>, value: f32) {
+    let bits = value.to_bits();
+    put_u16(buf, (bits >> 16) as u16);
+    put_u16(buf, bits as u16);
+}
+
+// Length-prefixed UTF-8, cut at `u16::MAX` bytes.
+pub fn put_str(buf: &mut Vec<u8>, value: &str) {
+    let mut len = ::std::cmp::min(value.len(), u16::MAX as usize);
+    while !value.is_char_boundary(len) {
+        len -= 1;
+    }
+    put_u16(buf, len as u16);
+    buf.extend_from_slice(&value.as_bytes()[..len]);
+}
+
+// Bounds-checked big-endian reader for message payloads.
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Reader<'a> {
+        Reader { buf: buf, pos: 0 }
+    }
+
+    pub fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    pub fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(get_u16)
+    }
+
+    pub fn f32(&mut self) -> Option<f32> {
+        let high = self.u16()? as u32;
+        let low = self.u16()? as u32;
+        Some(f32::from_bits(high << 16 | low))
+    }
+
+    pub fn string(&mut self) -> Option<String> {
+        let len = self.u16()? as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.pos + len > self.buf.len() {
+            return None;
+        }
+        let bytes = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Some(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(frame: Frame) {
+        assert_eq!(Frame::decode(&frame.encode()), Some(frame));
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        round_trip(Frame::Reliable(0xBEEF, vec![1, 2, 3]));
+        round_trip(Frame::Ack(0));
+        round_trip(Frame::Connect(u16::MAX));
+        round_trip(Frame::Accept(7));
+        round_trip(Frame::Ping(0x0102));
+        round_trip(Frame::Pong(0x0201));
+        round_trip(Frame::Unreliable(vec![0, 1, 2]));
+    }
+
+    #[test]
+    fn header_is_marker_kind_and_big_endian_argument() {
+        assert_eq!(
+            Frame::Ack(0x1234).encode(),
+            vec![FRAME_MARKER, KIND_ACK, 0x12, 0x34]
+        );
+        assert_eq!(
+            Frame::Reliable(1, vec![9]).encode(),
+            vec![FRAME_MARKER, KIND_RELIABLE, 0, 1, 9]
+        );
+    }
+
+    #[test]
+    fn message_extensions_pass_through_as_payloads() {
+        let payload = vec![FRAME_MARKER, KIND_MESSAGE, 1];
+        assert_eq!(Frame::decode(&payload), Some(Frame::Unreliable(payload)));
+    }
+
+    #[test]
+    fn malformed_frames_are_dropped() {
+        assert_eq!(Frame::decode(&[]), None);
+        assert_eq!(Frame::decode(&[FRAME_MARKER, KIND_ACK, 0]), None);
+        // A reliable frame with nothing in it.
+        assert_eq!(Frame::decode(&[FRAME_MARKER, KIND_RELIABLE, 0, 1]), None);
+        assert_eq!(Frame::decode(&[FRAME_MARKER, 0x7F, 0, 1]), None);
+    }
+
+    #[test]
+    fn reader_reads_what_was_written() {
+        let mut buf = vec![42];
+        put_u16(&mut buf, 0xABCD);
+        put_f32(&mut buf, -1.5);
+        put_str(&mut buf, "héllo");
+
+        let mut reader = Reader::new(&buf);
+        assert_eq!(reader.u8(), Some(42));
+        assert_eq!(reader.u16(), Some(0xABCD));
+        assert_eq!(reader.f32(), Some(-1.5));
+        assert_eq!(reader.string(), Some("héllo".to_string()));
+        assert_eq!(reader.u8(), None);
+    }
+
+    #[test]
+    fn reader_stops_at_the_end_of_the_buffer() {
+        // Claims five bytes of string but only carries two.
+        let buf = [0, 5, b'h', b'i'];
+        let mut reader = Reader::new(&buf);
+        assert_eq!(reader.string(), None);
+
+        let mut reader = Reader::new(&[1]);
+        assert_eq!(reader.u16(), None);
+    }
+
+    #[test]
+    fn long_strings_are_cut_on_a_char_boundary() {
+        let text = "é".repeat(40000);
+        let mut buf = Vec::new();
+        put_str(&mut buf, &text);
+
+        let read = Reader::new(&buf).string().unwrap();
+        assert_eq!(read.len(), u16::MAX as usize - 1);
+        assert!(text.starts_with(&read));
+    }
+}
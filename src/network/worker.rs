@@ -0,0 +1,289 @@
+use network::connection::{Action, ConnectionMonitor, ConnectionState};
+use network::message::Message;
+use network::reliable::ReliableChannel;
+use network::wire::Frame;
+use network::{MAX_DATAGRAM_SIZE, RECV_TIMEOUT_MS};
+use protocol;
+use protocol::enums::MessageType;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// After shutdown is requested the worker keeps resending unacknowledged
+// reliable messages for at most this long.
+const SHUTDOWN_LINGER_MS: u64 = 500;
+
+pub enum Command {
+    Send(Message),
+    SendReliable(Message),
+    Shutdown,
+}
+
+pub enum Event {
+    Message(Message),
+    State(ConnectionState),
+    Error(io::Error),
+}
+
+pub struct Worker {
+    socket: UdpSocket,
+    server_addr: SocketAddr,
+    commands: Receiver<Command>,
+    events: Sender<Event>,
+    recv_buffer: Vec<u8>,
+    reliable: ReliableChannel,
+    monitor: ConnectionMonitor,
+    // Reliable payloads queued while not connected; sent once the server
+    // accepts the next session.
+    held: Vec<Vec<u8>>,
+    ping_nonce: u16,
+    shutdown_at: Option<Instant>,
+}
+
+impl Worker {
+    pub fn new(
+        socket: UdpSocket,
+        server_addr: SocketAddr,
+        commands: Receiver<Command>,
+        events: Sender<Event>,
+    ) -> io::Result<Worker> {
+        socket.set_nonblocking(false)?;
+        socket.set_read_timeout(Some(Duration::from_millis(RECV_TIMEOUT_MS)))?;
+
+        Ok(Worker {
+            socket: socket,
+            server_addr: server_addr,
+            commands: commands,
+            events: events,
+            recv_buffer: vec![0; MAX_DATAGRAM_SIZE + 1],
+            reliable: ReliableChannel::new(),
+            monitor: ConnectionMonitor::new(Instant::now(), session_seed()),
+            held: Vec::new(),
+            ping_nonce: 0,
+            shutdown_at: None,
+        })
+    }
+
+    pub fn run(mut self) {
+        let action = self.monitor.connect(Instant::now());
+        self.perform(action);
+        self.emit(Event::State(self.monitor.state()));
+
+        loop {
+            let state = self.monitor.state();
+
+            if self.shutdown_at.is_none() && !self.send_pending() {
+                self.shutdown_at = Some(Instant::now());
+                self.on_shutdown();
+            }
+            if !self.recv() {
+                break;
+            }
+            self.retransmit();
+
+            if let Some(action) = self.monitor.tick(Instant::now()) {
+                self.perform(action);
+            }
+            if self.monitor.state() != state && !self.emit(Event::State(self.monitor.state())) {
+                break;
+            }
+
+            if let Some(shutdown_at) = self.shutdown_at {
+                let linger = Duration::from_millis(SHUTDOWN_LINGER_MS);
+                if self.reliable.is_idle() || shutdown_at.elapsed() >= linger {
+                    break;
+                }
+            }
+        }
+
+        self.monitor.disconnect(Instant::now());
+        self.emit(Event::State(self.monitor.state()));
+    }
+
+    fn perform(&mut self, action: Action) {
+        match action {
+            Action::SendConnect(session) => {
+                self.hold_unacked();
+                self.send_frame(&Frame::Connect(session));
+            }
+            Action::SendHeartbeat => {
+                self.ping_nonce = self.ping_nonce.wrapping_add(1);
+                let frame = Frame::Ping(self.ping_nonce);
+                self.send_frame(&frame);
+            }
+        }
+    }
+
+    // Registers as a listener on the freshly accepted session, then releases
+    // whatever reliable traffic piled up while disconnected.
+    fn on_connected(&mut self) {
+        let mut payloads = vec![protocol::pack(&MessageType::AddToListenersRequest)];
+        payloads.append(&mut self.held);
+        for payload in payloads {
+            self.send_reliable(payload);
+        }
+    }
+
+    // Starts a fresh channel for the next session. Whatever the old one never
+    // got acknowledged goes back in front of `held`, so it is sent again, in
+    // order, once the server accepts us. The listener registration is left
+    // out: `on_connected` always sends a new one.
+    fn hold_unacked(&mut self) {
+        let listen = protocol::pack(&MessageType::AddToListenersRequest);
+        let mut payloads: Vec<Vec<u8>> = self
+            .reliable
+            .take_unacked()
+            .into_iter()
+            .filter(|payload| *payload != listen)
+            .collect();
+        payloads.append(&mut self.held);
+        self.held = payloads;
+        self.reliable = ReliableChannel::new();
+    }
+
+    // Tells the server we are gone. Unsent held traffic is dropped: it was
+    // never part of an accepted session.
+    fn on_shutdown(&mut self) {
+        self.held.clear();
+        if self.monitor.state() == ConnectionState::Connected {
+            self.send_reliable(protocol::pack(&MessageType::RemoveFromListeners));
+        }
+    }
+
+    fn send_reliable(&mut self, payload: Vec<u8>) {
+        if self.monitor.state() != ConnectionState::Connected {
+            self.held.push(payload);
+            return;
+        }
+        let frame = self.reliable.send(payload, Instant::now());
+        self.send_frame(&frame);
+    }
+
+    // Sends everything the render loop has queued. Returns false once the
+    // render loop asked the worker to stop.
+    fn send_pending(&mut self) -> bool {
+        loop {
+            match self.commands.try_recv() {
+                Ok(Command::Send(msg)) => {
+                    let frame = Frame::Unreliable(msg.encode());
+                    self.send_frame(&frame);
+                }
+                Ok(Command::SendReliable(msg)) => self.send_reliable(msg.encode()),
+                Ok(Command::Shutdown) => return false,
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => return false,
+            }
+        }
+    }
+
+    fn retransmit(&mut self) {
+        match self.reliable.retransmit(Instant::now()) {
+            Ok(frames) => {
+                for frame in frames.iter() {
+                    self.send_frame(frame);
+                }
+            }
+            Err(seq) => {
+                println!("Reliable message {} was never acknowledged", seq);
+                self.hold_unacked();
+                self.monitor.failed(Instant::now());
+            }
+        }
+    }
+
+    fn send_frame(&mut self, frame: &Frame) {
+        if let Err(e) = self.socket.send_to(&frame.encode(), self.server_addr) {
+            self.emit(Event::Error(e));
+        }
+    }
+
+    // Blocks for at most `RECV_TIMEOUT_MS` and forwards whatever arrives.
+    // Returns false once the worker should stop.
+    fn recv(&mut self) -> bool {
+        match self.socket.recv_from(&mut self.recv_buffer) {
+            Ok((size, addr)) => {
+                if addr != self.server_addr {
+                    println!("Dropped datagram from unknown peer {:?}", addr);
+                    true
+                } else if size > MAX_DATAGRAM_SIZE {
+                    println!("Dropped oversize datagram from {:?}", addr);
+                    true
+                } else {
+                    self.monitor.heard(Instant::now());
+                    let frame = Frame::decode(&self.recv_buffer[..size]);
+                    self.handle_frame(frame)
+                }
+            }
+            Err(ref e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                true
+            }
+            Err(e) => self.emit(Event::Error(e)),
+        }
+    }
+
+    fn handle_frame(&mut self, frame: Option<Frame>) -> bool {
+        let connected = self.monitor.state() == ConnectionState::Connected;
+        match frame {
+            Some(Frame::Unreliable(payload)) => self.deliver(&payload),
+            // Reliable traffic belongs to a session; until one is accepted
+            // there is nothing to order it against.
+            Some(Frame::Reliable(seq, payload)) if connected => {
+                let (ack, delivered) = self.reliable.receive(seq, payload);
+                self.send_frame(&ack);
+                delivered.iter().all(|payload| self.deliver(payload))
+            }
+            Some(Frame::Ack(seq)) => {
+                self.reliable.ack(seq);
+                true
+            }
+            Some(Frame::Accept(session)) => {
+                if self.monitor.accepted(session, Instant::now()) {
+                    self.on_connected();
+                }
+                true
+            }
+            Some(Frame::Ping(nonce)) => {
+                self.send_frame(&Frame::Pong(nonce));
+                true
+            }
+            Some(Frame::Reliable(..)) | Some(Frame::Connect(..)) | Some(Frame::Pong(..)) => true,
+            None => {
+                println!("Dropped malformed frame");
+                true
+            }
+        }
+    }
+
+    fn deliver(&mut self, payload: &Vec<u8>) -> bool {
+        match Message::decode(payload) {
+            Some(msg) => {
+                if let Message::Kicked(_) = msg {
+                    // Disconnected never reconnects on its own.
+                    self.held.clear();
+                    self.monitor.disconnect(Instant::now());
+                }
+                self.emit(Event::Message(msg))
+            }
+            None => {
+                println!("Dropped unknown message");
+                true
+            }
+        }
+    }
+
+    fn emit(&self, event: Event) -> bool {
+        self.events.send(event).is_ok()
+    }
+}
+
+// Session ids only need to differ between runs, so that a restarted client
+// is not mistaken for a repeat of its previous handshake.
+fn session_seed() -> u16 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(since_epoch) => (since_epoch.subsec_nanos() >> 10) as u16,
+        Err(_) => 0,
+    }
+}
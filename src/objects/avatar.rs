@@ -0,0 +1,36 @@
+use rust_chunk::interpolation::SnapshotBuffer;
+use objects::{Circle, Transform};
+use rust_chunk::render::batch::Colour;
+
+// Picked by member id, so everyone sees a given player in the same colour.
+const PALETTE: [(f32, f32, f32); 6] = [
+    (0.4, 0.8, 1.0),
+    (1.0, 0.5, 0.4),
+    (0.5, 1.0, 0.5),
+    (0.9, 0.5, 1.0),
+    (1.0, 0.7, 0.2),
+    (0.9, 0.9, 0.9),
+];
+
+// Another member of the room. `circle.transform` is where it is as of the
+// last update, in the same world coordinates the server simulates, trailing
+// the positions in `snapshots` by the interpolation delay. `previous` is
+// where it was the update before.
+pub struct Avatar {
+    pub circle: Circle,
+    pub previous: Transform,
+    pub colour: Colour,
+    pub snapshots: SnapshotBuffer,
+}
+
+impl Avatar {
+    pub fn new(id: u16) -> Avatar {
+        let (r, g, b) = PALETTE[id as usize % PALETTE.len()];
+        Avatar {
+            circle: Circle::new(0f32, 0f32, 10f32),
+            previous: Transform::at(0f32, 0f32),
+            colour: [r, g, b, 1.0],
+            snapshots: SnapshotBuffer::new(),
+        }
+    }
+}
@@ -1,28 +1,39 @@
-use cgmath::Point2;
-use graphics::Gfx;
-use gfx_gl::{Gl, LINE_STRIP};
+use objects::Transform;
+use rust_chunk::render::batch::Colour;
+use rust_chunk::render::{Batch, ProgramId};
+
+const SEGMENTS: u32 = 24;
+const LINE_WIDTH: f32 = 1.5;
 
 pub struct Circle {
-    pos: Point2<f32>,
-    pub gpos: Point2<f32>,
-    r: f32,
-    gfx: Box<Gfx>,
+    pub transform: Transform,
+    pub r: f32,
 }
 
 impl Circle {
-    pub fn new(gl: &Gl, x: f32, y: f32, r: f32) -> Circle {
+    pub fn new(x: f32, y: f32, r: f32) -> Circle {
         Circle {
-            pos: Point2 { x: x, y: y },
-            gpos: Point2 { x: 0f32, y: 0f32 },
+            transform: Transform::at(x, y),
             r: r,
-            gfx: Box::new(Gfx::build_circle_sample(gl, r, 24)),
         }
     }
 
-    pub fn draw(&self) {
-        unsafe {
-            self.gfx.gl.BindVertexArray(self.gfx.vao);
-            self.gfx.gl.DrawArrays(LINE_STRIP, 0, 25);
-        }
+    // Queues the outline in `batch`. `transform` is usually a blend of this
+    // circle's transform over the last two updates.
+    pub fn draw_with(
+        &self,
+        batch: &mut Batch,
+        material: ProgramId,
+        transform: &Transform,
+        colour: Colour,
+    ) {
+        batch.ring(
+            material,
+            &transform.matrix(),
+            self.r,
+            LINE_WIDTH,
+            SEGMENTS,
+            colour,
+        );
     }
 }
@@ -1,5 +1,9 @@
+pub use self::avatar::*;
 pub use self::circle::*;
 pub use self::rectangle::*;
+pub use self::transform::*;
 
+mod avatar;
 mod circle;
 mod rectangle;
+mod transform;
@@ -1,20 +1,22 @@
-use cgmath::Point2;
+use cgmath::{Matrix4, Point2};
 use graphics::Gfx;
-use gfx_gl::{Gl, TRIANGLE_STRIP};
 use collision::Aabb2;
+use objects::Transform;
+use rust_chunk::render::{Primitive, RefRenderer};
+use shaders::Shader;
 
 pub struct Rectangle {
-    pos: Point2<f32>,
-    aabb: Aabb2<f32>,
+    pub transform: Transform,
     gfx: Box<Gfx>,
 }
 
 impl Rectangle {
-    pub fn new(gl: &Gl, x: f32, y: f32, w: f32, h: f32) -> Rectangle {
+    // `x` and `y` are the centre.
+    pub fn new(renderer: &RefRenderer, x: f32, y: f32, w: f32, h: f32) -> Rectangle {
         let wd = w * 0.5;
         let hd = h * 0.5;
-        let min = Point2::new(x - wd, y - hd);
-        let max = Point2::new(x + wd, y + hd);
+        let min = Point2::new(-wd, -hd);
+        let max = Point2::new(wd, hd);
         let aabb = Aabb2::new(min, max);
         let vertices: Vec<f32> = aabb.to_corners()
             .iter()
@@ -22,16 +24,14 @@ impl Rectangle {
             .collect();
 
         Rectangle {
-            pos: Point2 { x: x, y: y },
-            aabb: aabb,
-            gfx: Box::new(Gfx::build_rectangle_sample(gl, &vertices)),
+            transform: Transform::at(x, y),
+            gfx: Box::new(Gfx::build_rectangle_sample(renderer, &vertices)),
         }
     }
 
-    pub fn draw(&self) {
-        unsafe {
-            self.gfx.gl.BindVertexArray(self.gfx.vao);
-            self.gfx.gl.DrawArrays(TRIANGLE_STRIP, 0, 4);
-        }
+    // `view` maps scene coordinates to clip space; `shader` must be in use.
+    pub fn draw(&self, shader: &Shader, view: &Matrix4<f32>) {
+        shader.uniform_matrix4fv("supermatrix", &(view * self.transform.matrix()));
+        self.gfx.draw(Primitive::TriangleStrip, 4);
     }
 }
@@ -0,0 +1,35 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point2, Rad, Vector2};
+
+// Where a drawable sits in its scene. Vertices are built around the origin
+// and placed by the model matrix: scaled, then rotated, then moved.
+#[derive(Copy, Clone, Debug)]
+pub struct Transform {
+    pub position: Point2<f32>,
+    pub rotation: Rad<f32>,
+    pub scale: Vector2<f32>,
+}
+
+impl Transform {
+    pub fn at(x: f32, y: f32) -> Transform {
+        Transform {
+            position: Point2::new(x, y),
+            rotation: Rad(0f32),
+            scale: Vector2::new(1f32, 1f32),
+        }
+    }
+
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.position.to_vec().extend(0f32))
+            * Matrix4::from_angle_z(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, 1f32)
+    }
+
+    // The transform `t` of the way from `self` to `to`.
+    pub fn lerp(&self, to: &Transform, t: f32) -> Transform {
+        Transform {
+            position: Point2::from_vec(self.position.to_vec().lerp(to.position.to_vec(), t)),
+            rotation: self.rotation + (to.rotation - self.rotation) * t,
+            scale: self.scale.lerp(to.scale, t),
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use cgmath::{InnerSpace, Matrix4, Point2, Vector2, Vector4};
+use render::{BufferId, Primitive, ProgramId, RefRenderer, Uniform};
+use std::f32::consts::PI;
+
+// Floats per batched vertex: position x, y, then colour r, g, b, a.
+pub const VERTEX_FLOATS: usize = 6;
+
+pub type Colour = [f32; 4];
+
+// Collects shapes for a frame and draws them with as few calls as possible:
+// every shape becomes triangles, already transformed and coloured, in one
+// shared stream buffer, and each material (shader program) is one draw.
+// Materials are drawn in the order they were first used.
+pub struct Batch {
+    renderer: RefRenderer,
+    buffer: BufferId,
+    materials: Vec<(ProgramId, Vec<f32>)>,
+}
+
+impl Batch {
+    pub fn new(renderer: &RefRenderer) -> Batch {
+        Batch {
+            renderer: renderer.clone(),
+            buffer: renderer.borrow_mut().create_stream_buffer(),
+            materials: Vec::new(),
+        }
+    }
+
+    // A `w` by `h` rectangle centred on the origin of `model`.
+    pub fn quad(
+        &mut self,
+        material: ProgramId,
+        model: &Matrix4<f32>,
+        w: f32,
+        h: f32,
+        colour: Colour,
+    ) {
+        let (wd, hd) = (w * 0.5, h * 0.5);
+        let corners = [
+            transform(model, -wd, -hd),
+            transform(model, wd, -hd),
+            transform(model, wd, hd),
+            transform(model, -wd, hd),
+        ];
+        let vertices = self.vertices(material);
+        put_quad(vertices, &corners, colour);
+    }
+
+    // A filled disc of radius `r` around the origin of `model`.
+    pub fn circle(
+        &mut self,
+        material: ProgramId,
+        model: &Matrix4<f32>,
+        r: f32,
+        segments: u32,
+        colour: Colour,
+    ) {
+        let centre = transform(model, 0f32, 0f32);
+        let rim = rim(model, r, segments);
+        let vertices = self.vertices(material);
+        for i in 0..segments as usize {
+            put_vertex(vertices, centre, colour);
+            put_vertex(vertices, rim[i], colour);
+            put_vertex(vertices, rim[i + 1], colour);
+        }
+    }
+
+    // The outline of a circle, `width` thick and centred on radius `r`.
+    pub fn ring(
+        &mut self,
+        material: ProgramId,
+        model: &Matrix4<f32>,
+        r: f32,
+        width: f32,
+        segments: u32,
+        colour: Colour,
+    ) {
+        let inner = rim(model, r - width * 0.5, segments);
+        let outer = rim(model, r + width * 0.5, segments);
+        let vertices = self.vertices(material);
+        for i in 0..segments as usize {
+            put_quad(
+                vertices,
+                &[inner[i], outer[i], outer[i + 1], inner[i + 1]],
+                colour,
+            );
+        }
+    }
+
+    // A segment `width` thick, in the batch's own coordinates.
+    pub fn line(
+        &mut self,
+        material: ProgramId,
+        from: Point2<f32>,
+        to: Point2<f32>,
+        width: f32,
+        colour: Colour,
+    ) {
+        let along = to - from;
+        if along.magnitude2() == 0f32 {
+            return;
+        }
+        let side = Vector2::new(-along.y, along.x).normalize() * (width * 0.5);
+        let vertices = self.vertices(material);
+        put_quad(
+            vertices,
+            &[from - side, to - side, to + side, from + side],
+            colour,
+        );
+    }
+
+    // Uploads everything queued and draws it through `view`, then starts
+    // over for the next frame.
+    pub fn flush(&mut self, view: &Matrix4<f32>) {
+        let mut stream = Vec::new();
+        let mut draws = Vec::new();
+        for &(material, ref vertices) in self.materials.iter() {
+            let first = (stream.len() / VERTEX_FLOATS) as i32;
+            let count = (vertices.len() / VERTEX_FLOATS) as i32;
+            stream.extend_from_slice(vertices);
+            draws.push((material, first, count));
+        }
+        self.materials.clear();
+        if draws.is_empty() {
+            return;
+        }
+
+        let mut renderer = self.renderer.borrow_mut();
+        renderer.stream(self.buffer, &stream);
+        for (material, first, count) in draws {
+            renderer.use_program(material);
+            renderer.uniform(material, "supermatrix", Uniform::Mat4(*view));
+            renderer.draw(self.buffer, Primitive::Triangles, first, count);
+        }
+    }
+
+    fn vertices(&mut self, material: ProgramId) -> &mut Vec<f32> {
+        let i = match self.materials.iter().position(|&(id, _)| id == material) {
+            Some(i) => i,
+            None => {
+                self.materials.push((material, Vec::new()));
+                self.materials.len() - 1
+            }
+        };
+        &mut self.materials[i].1
+    }
+}
+
+impl Drop for Batch {
+    fn drop(&mut self) {
+        self.renderer.borrow_mut().delete_buffer(self.buffer);
+    }
+}
+
+fn transform(model: &Matrix4<f32>, x: f32, y: f32) -> Point2<f32> {
+    let p = model * Vector4::new(x, y, 0f32, 1f32);
+    Point2::new(p.x, p.y)
+}
+
+// `segments + 1` points around a circle, the last repeating the first.
+fn rim(model: &Matrix4<f32>, r: f32, segments: u32) -> Vec<Point2<f32>> {
+    (0..segments + 1)
+        .map(|i| {
+            let angle = 2.0 * PI * (i % segments) as f32 / segments as f32;
+            transform(model, angle.sin() * r, angle.cos() * r)
+        })
+        .collect()
+}
+
+// Two triangles over four corners given in order around the quad.
+fn put_quad(vertices: &mut Vec<f32>, corners: &[Point2<f32>; 4], colour: Colour) {
+    for &i in [0, 1, 2, 0, 2, 3].iter() {
+        put_vertex(vertices, corners[i], colour);
+    }
+}
+
+fn put_vertex(vertices: &mut Vec<f32>, p: Point2<f32>, colour: Colour) {
+    vertices.extend_from_slice(&[p.x, p.y, colour[0], colour[1], colour[2], colour[3]]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{SquareMatrix, Vector3};
+    use render::{Command, RecordingRenderer, Renderer};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const WHITE: Colour = [1f32; 4];
+
+    fn recorded() -> (Rc<RefCell<RecordingRenderer>>, Batch) {
+        let recording = Rc::new(RefCell::new(RecordingRenderer::new()));
+        let renderer: RefRenderer = recording.clone();
+        let batch = Batch::new(&renderer);
+        recording.borrow_mut().take_commands();
+        (recording, batch)
+    }
+
+    #[test]
+    fn one_draw_per_material_in_first_use_order() {
+        let (recording, mut batch) = recorded();
+        let identity = Matrix4::identity();
+        let (a, b) = (ProgramId(1), ProgramId(2));
+
+        batch.quad(b, &identity, 1f32, 1f32, WHITE);
+        batch.circle(a, &identity, 1f32, 3, WHITE);
+        batch.ring(b, &identity, 1f32, 0.5, 4, WHITE);
+        batch.line(
+            a,
+            Point2::new(0f32, 0f32),
+            Point2::new(1f32, 0f32),
+            1f32,
+            WHITE,
+        );
+        let view = Matrix4::from_scale(2f32);
+        batch.flush(&view);
+
+        let commands = recording.borrow_mut().take_commands();
+        let buffer = BufferId(1);
+        match commands[0] {
+            Command::Stream(id, ref vertices) => {
+                assert_eq!(id, buffer);
+                // 6 + 24 vertices for `b`, then 9 + 6 for `a`.
+                assert_eq!(vertices.len(), 45 * VERTEX_FLOATS);
+            }
+            ref command => panic!("expected a stream, got {:?}", command),
+        }
+        assert_eq!(
+            &commands[1..],
+            &[
+                Command::UseProgram(b),
+                Command::Uniform(b, "supermatrix".to_string(), Uniform::Mat4(view)),
+                Command::Draw(buffer, Primitive::Triangles, 0, 30),
+                Command::UseProgram(a),
+                Command::Uniform(a, "supermatrix".to_string(), Uniform::Mat4(view)),
+                Command::Draw(buffer, Primitive::Triangles, 30, 15),
+            ]
+        );
+    }
+
+    #[test]
+    fn flush_starts_the_next_frame_empty() {
+        let (recording, mut batch) = recorded();
+        batch.quad(ProgramId(1), &Matrix4::identity(), 1f32, 1f32, WHITE);
+        batch.flush(&Matrix4::identity());
+        recording.borrow_mut().take_commands();
+
+        batch.flush(&Matrix4::identity());
+        assert!(recording.borrow().commands().is_empty());
+    }
+
+    #[test]
+    fn shapes_are_placed_and_coloured_per_vertex() {
+        let (recording, mut batch) = recorded();
+        let model = Matrix4::from_translation(Vector3::new(10f32, 20f32, 0f32));
+        let red = [1f32, 0f32, 0f32, 1f32];
+        batch.quad(ProgramId(1), &model, 4f32, 2f32, red);
+        // Zero length, so nothing to draw.
+        batch.line(
+            ProgramId(1),
+            Point2::new(1f32, 1f32),
+            Point2::new(1f32, 1f32),
+            1f32,
+            WHITE,
+        );
+        batch.flush(&Matrix4::identity());
+
+        let vertices = recording.borrow().vertices(BufferId(1)).unwrap().to_vec();
+        let corners: Vec<(f32, f32)> = vertices
+            .chunks(VERTEX_FLOATS)
+            .map(|vertex| (vertex[0], vertex[1]))
+            .collect();
+        assert_eq!(
+            corners,
+            vec![
+                (8f32, 19f32),
+                (12f32, 19f32),
+                (12f32, 21f32),
+                (8f32, 19f32),
+                (12f32, 21f32),
+                (8f32, 21f32)
+            ]
+        );
+        assert!(vertices
+            .chunks(VERTEX_FLOATS)
+            .all(|vertex| vertex[2..] == red[..]));
+    }
+
+    #[test]
+    fn dropping_the_batch_frees_its_buffer() {
+        let (recording, batch) = recorded();
+        assert_eq!(recording.borrow().live().buffers, 1);
+        drop(batch);
+        assert_eq!(recording.borrow().live().buffers, 0);
+    }
+}
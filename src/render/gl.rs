@@ -0,0 +1,326 @@
+use cgmath::{Array, Matrix};
+use gfx_gl::types::*;
+use gfx_gl::*;
+use render::batch::VERTEX_FLOATS;
+use render::{BufferId, FramebufferId, LiveResources, Primitive, ProgramId, Renderer, Uniform};
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::mem::{size_of, size_of_val};
+
+pub struct GlRenderer {
+    gl: Gl,
+    // The vertex buffer behind each vertex array handed out as a buffer.
+    buffers: HashMap<BufferId, GLuint>,
+    // How many bytes each stream buffer has room for.
+    streams: HashMap<BufferId, isize>,
+    // The vertex and fragment shader linked into each program.
+    programs: HashMap<ProgramId, (GLuint, GLuint)>,
+    // The colour renderbuffer attached to each framebuffer.
+    framebuffers: HashMap<FramebufferId, GLuint>,
+    bound: Option<FramebufferId>,
+}
+
+impl GlRenderer {
+    // `gl` must be loaded for a context that is current on this thread.
+    pub fn new(gl: &Gl) -> GlRenderer {
+        GlRenderer {
+            gl: gl.clone(),
+            buffers: HashMap::new(),
+            streams: HashMap::new(),
+            programs: HashMap::new(),
+            framebuffers: HashMap::new(),
+            bound: None,
+        }
+    }
+
+    fn build_shader(&self, type_: u32, source: &str) -> u32 {
+        unsafe {
+            let id = self.gl.CreateShader(type_);
+            self.gl.ShaderSource(
+                id,
+                1,
+                &(source.as_ptr() as *const i8),
+                &(source.len() as i32),
+            );
+            self.gl.CompileShader(id);
+            self.shader_log(id);
+            id
+        }
+    }
+
+    fn shader_log(&self, id: u32) {
+        unsafe {
+            let mut compile_status: i32 = 0;
+            self.gl.GetShaderiv(id, COMPILE_STATUS, &mut compile_status);
+            println!("Compile status: {:?}", compile_status > 0);
+            let mut log: Vec<u8> = vec![0; 512];
+            let mut length: i32 = 0;
+            self.gl
+                .GetShaderInfoLog(id, 512, &mut length, log.as_mut_ptr() as *mut i8);
+            log.truncate(length as usize);
+            println!("Log length: {:?}", length);
+            println!("Log: {:?}", String::from_utf8_lossy(&log));
+            println!("isShader: {:?} -> {:?}", id, self.gl.IsShader(id) > 0);
+            println!();
+        }
+    }
+
+    fn program_log(&self, id: u32) {
+        unsafe {
+            let mut link_status: i32 = 0;
+            self.gl.GetProgramiv(id, LINK_STATUS, &mut link_status);
+            println!("Link status: {:?}", link_status > 0);
+            let mut log: Vec<u8> = vec![0; 512];
+            let mut length: i32 = 0;
+            self.gl
+                .GetProgramInfoLog(id, 512, &mut length, log.as_mut_ptr() as *mut i8);
+            log.truncate(length as usize);
+            println!("Log length: {:?}", length);
+            println!("Log: {:?}", String::from_utf8_lossy(&log));
+            println!();
+        }
+    }
+}
+
+impl Renderer for GlRenderer {
+    fn create_buffer(&mut self, vertices: &[f32]) -> BufferId {
+        unsafe {
+            let mut vao: GLuint = 0;
+            self.gl.GenVertexArrays(1, &mut vao);
+            self.gl.BindVertexArray(vao);
+
+            let mut vbo: GLuint = 0;
+            self.gl.GenBuffers(1, &mut vbo);
+            self.gl.BindBuffer(ARRAY_BUFFER, vbo);
+            self.gl.BufferData(
+                ARRAY_BUFFER,
+                4 * vertices.len() as isize,
+                vertices.as_ptr() as *const GLvoid,
+                STATIC_DRAW,
+            );
+
+            self.gl.EnableVertexAttribArray(0);
+            self.gl
+                .VertexAttribPointer(0, 3, FLOAT, FALSE, 0, ::std::ptr::null());
+
+            self.buffers.insert(BufferId(vao), vbo);
+            BufferId(vao)
+        }
+    }
+
+    fn create_stream_buffer(&mut self) -> BufferId {
+        let stride = (VERTEX_FLOATS * size_of::<f32>()) as i32;
+        unsafe {
+            let mut vao: GLuint = 0;
+            self.gl.GenVertexArrays(1, &mut vao);
+            self.gl.BindVertexArray(vao);
+
+            let mut vbo: GLuint = 0;
+            self.gl.GenBuffers(1, &mut vbo);
+            self.gl.BindBuffer(ARRAY_BUFFER, vbo);
+
+            self.gl.EnableVertexAttribArray(0);
+            self.gl
+                .VertexAttribPointer(0, 2, FLOAT, FALSE, stride, ::std::ptr::null());
+            self.gl.EnableVertexAttribArray(1);
+            self.gl.VertexAttribPointer(
+                1,
+                4,
+                FLOAT,
+                FALSE,
+                stride,
+                (2 * size_of::<f32>()) as *const GLvoid,
+            );
+
+            self.buffers.insert(BufferId(vao), vbo);
+            self.streams.insert(BufferId(vao), 0);
+            BufferId(vao)
+        }
+    }
+
+    fn stream(&mut self, buffer: BufferId, vertices: &[f32]) {
+        let (vbo, capacity) = match (self.buffers.get(&buffer), self.streams.get_mut(&buffer)) {
+            (Some(&vbo), Some(capacity)) => (vbo, capacity),
+            _ => panic!("{:?} is not a stream buffer", buffer),
+        };
+        let size = size_of_val(vertices) as isize;
+        unsafe {
+            self.gl.BindBuffer(ARRAY_BUFFER, vbo);
+            if size > *capacity {
+                // Twice what is needed, so a slowly growing scene does not
+                // reallocate every frame.
+                *capacity = size * 2;
+            }
+            // Fresh storage every time: the driver need not wait for draws
+            // still reading the previous frame's vertices.
+            self.gl
+                .BufferData(ARRAY_BUFFER, *capacity, ::std::ptr::null(), STREAM_DRAW);
+            self.gl
+                .BufferSubData(ARRAY_BUFFER, 0, size, vertices.as_ptr() as *const GLvoid);
+        }
+    }
+
+    fn create_program(&mut self, vertex: &str, fragment: &str) -> ProgramId {
+        println!("##### Vertex Shader #####");
+        let vs = self.build_shader(VERTEX_SHADER, vertex);
+        println!("##### Fragment Shader #####");
+        let fs = self.build_shader(FRAGMENT_SHADER, fragment);
+
+        println!("##### Link Program #####");
+        unsafe {
+            let id = self.gl.CreateProgram();
+            self.gl.AttachShader(id, vs);
+            self.gl.AttachShader(id, fs);
+            self.gl.LinkProgram(id);
+            self.program_log(id);
+            self.programs.insert(ProgramId(id), (vs, fs));
+            ProgramId(id)
+        }
+    }
+
+    fn delete_buffer(&mut self, buffer: BufferId) {
+        self.streams.remove(&buffer);
+        if let Some(vbo) = self.buffers.remove(&buffer) {
+            unsafe {
+                self.gl.DeleteBuffers(1, &vbo);
+                self.gl.DeleteVertexArrays(1, &buffer.0);
+            }
+        }
+    }
+
+    fn delete_program(&mut self, program: ProgramId) {
+        if let Some((vs, fs)) = self.programs.remove(&program) {
+            unsafe {
+                self.gl.DeleteProgram(program.0);
+                self.gl.DeleteShader(vs);
+                self.gl.DeleteShader(fs);
+            }
+        }
+    }
+
+    fn use_program(&mut self, program: ProgramId) {
+        unsafe {
+            self.gl.UseProgram(program.0);
+        }
+    }
+
+    fn uniform(&mut self, program: ProgramId, name: &str, value: Uniform) {
+        let uniform_name = CString::new(name).unwrap();
+        unsafe {
+            let location = self.gl.GetUniformLocation(program.0, uniform_name.as_ptr());
+            match value {
+                Uniform::Float(value) => self.gl.Uniform1f(location, value),
+                Uniform::Vec2(ref vector) => self.gl.Uniform2fv(location, 1, vector.as_ptr()),
+                Uniform::Vec3(ref vector) => self.gl.Uniform3fv(location, 1, vector.as_ptr()),
+                Uniform::Mat4(ref matrix) => {
+                    self.gl
+                        .UniformMatrix4fv(location, 1, FALSE, matrix.as_ptr())
+                }
+            }
+        }
+    }
+
+    fn clear(&mut self, red: f32, green: f32, blue: f32, alpha: f32) {
+        unsafe {
+            self.gl.ClearColor(red, green, blue, alpha);
+            self.gl.Clear(COLOR_BUFFER_BIT);
+        }
+    }
+
+    fn draw(&mut self, buffer: BufferId, primitive: Primitive, first: i32, count: i32) {
+        let mode = match primitive {
+            Primitive::Triangles => TRIANGLES,
+            Primitive::TriangleStrip => TRIANGLE_STRIP,
+            Primitive::LineStrip => LINE_STRIP,
+        };
+        unsafe {
+            self.gl.BindVertexArray(buffer.0);
+            self.gl.DrawArrays(mode, first, count);
+        }
+    }
+
+    fn create_framebuffer(&mut self, width: u32, height: u32) -> FramebufferId {
+        let (mut fbo, mut rbo) = (0, 0);
+        unsafe {
+            self.gl.GenRenderbuffers(1, &mut rbo);
+            self.gl.BindRenderbuffer(RENDERBUFFER, rbo);
+            self.gl
+                .RenderbufferStorage(RENDERBUFFER, RGBA8, width as i32, height as i32);
+
+            self.gl.GenFramebuffers(1, &mut fbo);
+            self.gl.BindFramebuffer(FRAMEBUFFER, fbo);
+            self.gl
+                .FramebufferRenderbuffer(FRAMEBUFFER, COLOR_ATTACHMENT0, RENDERBUFFER, rbo);
+            let status = self.gl.CheckFramebufferStatus(FRAMEBUFFER);
+            if status != FRAMEBUFFER_COMPLETE {
+                println!("Framebuffer {} is incomplete: {:#x}", fbo, status);
+            }
+
+            let bound = self.bound.map_or(0, |framebuffer| framebuffer.0);
+            self.gl.BindFramebuffer(FRAMEBUFFER, bound);
+        }
+        self.framebuffers.insert(FramebufferId(fbo), rbo);
+        FramebufferId(fbo)
+    }
+
+    fn delete_framebuffer(&mut self, framebuffer: FramebufferId) {
+        if self.bound == Some(framebuffer) {
+            self.bind_framebuffer(None);
+        }
+        if let Some(rbo) = self.framebuffers.remove(&framebuffer) {
+            unsafe {
+                self.gl.DeleteFramebuffers(1, &framebuffer.0);
+                self.gl.DeleteRenderbuffers(1, &rbo);
+            }
+        }
+    }
+
+    fn bind_framebuffer(&mut self, framebuffer: Option<FramebufferId>) {
+        self.bound = framebuffer;
+        unsafe {
+            self.gl.BindFramebuffer(
+                FRAMEBUFFER,
+                framebuffer.map_or(0, |framebuffer| framebuffer.0),
+            );
+        }
+    }
+
+    // Reads the bound framebuffer or, for the window, the back buffer, so
+    // call it before swapping.
+    fn read_pixels(&mut self, width: u32, height: u32) -> Vec<u8> {
+        let stride = width as usize * 4;
+        let mut pixels = vec![0u8; stride * height as usize];
+        unsafe {
+            self.gl.ReadBuffer(match self.bound {
+                Some(_) => COLOR_ATTACHMENT0,
+                None => BACK,
+            });
+            self.gl.PixelStorei(PACK_ALIGNMENT, 1);
+            self.gl.ReadPixels(
+                0,
+                0,
+                width as i32,
+                height as i32,
+                RGBA,
+                UNSIGNED_BYTE,
+                pixels.as_mut_ptr() as *mut GLvoid,
+            );
+        }
+        // GL counts rows from the bottom.
+        let mut flipped = Vec::with_capacity(pixels.len());
+        for row in pixels.chunks(stride).rev() {
+            flipped.extend_from_slice(row);
+        }
+        flipped
+    }
+
+    fn live(&self) -> LiveResources {
+        LiveResources {
+            vertex_arrays: self.buffers.len(),
+            buffers: self.buffers.len(),
+            shaders: self.programs.len() * 2,
+            programs: self.programs.len(),
+            framebuffers: self.framebuffers.len(),
+        }
+    }
+}
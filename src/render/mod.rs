@@ -0,0 +1,95 @@
+// Everything the client draws goes through a `Renderer`, so scenes and
+// drawables never touch GL themselves. `GlRenderer` draws for real;
+// `RecordingRenderer` only writes down what it was asked to do, which is
+// enough to check scene logic and layout without a GPU.
+
+use cgmath::{Matrix4, Vector2, Vector3};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub use self::batch::Batch;
+pub use self::gl::GlRenderer;
+pub use self::recording::{Command, RecordingRenderer};
+
+pub mod batch;
+pub mod png;
+
+mod gl;
+mod recording;
+
+pub type RefRenderer = Rc<RefCell<dyn Renderer>>;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BufferId(pub u32);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ProgramId(pub u32);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FramebufferId(pub u32);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Primitive {
+    Triangles,
+    TriangleStrip,
+    LineStrip,
+}
+
+// GPU objects a renderer has created and not yet deleted. Anything still
+// alive once every scene is gone has leaked.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LiveResources {
+    pub vertex_arrays: usize,
+    pub buffers: usize,
+    pub shaders: usize,
+    pub programs: usize,
+    // Each with the colour renderbuffer it draws into.
+    pub framebuffers: usize,
+}
+
+impl LiveResources {
+    pub fn total(&self) -> usize {
+        self.vertex_arrays + self.buffers + self.shaders + self.programs + self.framebuffers
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Uniform {
+    Float(f32),
+    Vec2(Vector2<f32>),
+    Vec3(Vector3<f32>),
+    Mat4(Matrix4<f32>),
+}
+
+pub trait Renderer {
+    // Uploads vertex positions, three floats each, into a new buffer.
+    fn create_buffer(&mut self, vertices: &[f32]) -> BufferId;
+    // An empty buffer for vertices that change every frame, laid out as
+    // `batch::VERTEX_FLOATS`: a 2D position then an RGBA colour.
+    fn create_stream_buffer(&mut self) -> BufferId;
+    // Replaces the contents of a stream buffer.
+    fn stream(&mut self, buffer: BufferId, vertices: &[f32]);
+    // Frees a buffer of either kind. The id must not be used again.
+    fn delete_buffer(&mut self, buffer: BufferId);
+    fn create_program(&mut self, vertex: &str, fragment: &str) -> ProgramId;
+    fn delete_program(&mut self, program: ProgramId);
+    fn use_program(&mut self, program: ProgramId);
+    // Sets a uniform of `program`, which must be in use.
+    fn uniform(&mut self, program: ProgramId, name: &str, value: Uniform);
+    fn clear(&mut self, red: f32, green: f32, blue: f32, alpha: f32);
+    // Draws `count` vertices of `buffer`, starting at vertex `first`, with
+    // the program in use.
+    fn draw(&mut self, buffer: BufferId, primitive: Primitive, first: i32, count: i32);
+    // An offscreen target with an RGBA colour buffer of this size. Unlike a
+    // window, whose pixels are only defined where it is visible, it can
+    // always be read back.
+    fn create_framebuffer(&mut self, width: u32, height: u32) -> FramebufferId;
+    fn delete_framebuffer(&mut self, framebuffer: FramebufferId);
+    // Where drawing and `read_pixels` go from now on; `None` is the window.
+    // The viewport stays as it is, so the framebuffer should be window
+    // sized.
+    fn bind_framebuffer(&mut self, framebuffer: Option<FramebufferId>);
+    // RGBA pixels of the frame drawn so far, top row first.
+    fn read_pixels(&mut self, width: u32, height: u32) -> Vec<u8>;
+    fn live(&self) -> LiveResources;
+}
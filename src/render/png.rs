@@ -0,0 +1,186 @@
+// Just enough PNG to save screenshots: 8-bit RGBA, no filtering, and zlib
+// data in stored (uncompressed) deflate blocks, so nothing beyond std is
+// needed. Files come out about as large as the raw pixels.
+
+use std::fs::File;
+use std::io::{self, Write};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+const MAX_STORED_BLOCK: usize = 65535;
+
+// `rgba` holds `height` rows of `width` pixels, top row first.
+pub fn encode(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    let stride = width as usize * 4;
+    assert_eq!(rgba.len(), stride * height as usize);
+
+    let mut header = Vec::with_capacity(13);
+    put_u32(&mut header, width);
+    put_u32(&mut header, height);
+    // Bit depth 8, colour type RGBA, default compression, filter and no
+    // interlacing.
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    // Every scanline starts with its filter type, 0 for none.
+    let mut scanlines = Vec::with_capacity((stride + 1) * height as usize);
+    for y in 0..height as usize {
+        scanlines.push(0);
+        scanlines.extend_from_slice(&rgba[y * stride..(y + 1) * stride]);
+    }
+
+    let mut png = SIGNATURE.to_vec();
+    put_chunk(&mut png, b"IHDR", &header);
+    put_chunk(&mut png, b"IDAT", &zlib_stored(&scanlines));
+    put_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+pub fn write(path: &str, width: u32, height: u32, rgba: &[u8]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(&encode(width, height, rgba))
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(if blocks.peek().is_none() { 1 } else { 0 });
+        let len = block.len() as u16;
+        out.extend_from_slice(&[len as u8, (len >> 8) as u8]);
+        out.extend_from_slice(&[!len as u8, (!len >> 8) as u8]);
+        out.extend_from_slice(block);
+    }
+    put_u32(&mut out, adler32(data));
+    out
+}
+
+fn put_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    put_u32(png, data.len() as u32);
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    put_u32(png, crc);
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&[
+        (value >> 24) as u8,
+        (value >> 16) as u8,
+        (value >> 8) as u8,
+        value as u8,
+    ]);
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_u32(buf: &[u8]) -> u32 {
+        (buf[0] as u32) << 24 | (buf[1] as u32) << 16 | (buf[2] as u32) << 8 | buf[3] as u32
+    }
+
+    // Reads back what `encode` writes, checking every checksum on the way:
+    // returns the size from IHDR and the pixels with the filter bytes
+    // removed.
+    fn decode(png: &[u8]) -> (u32, u32, Vec<u8>) {
+        assert_eq!(&png[..8], &SIGNATURE);
+        let mut pos = 8;
+        let mut chunks = Vec::new();
+        while pos < png.len() {
+            let len = get_u32(&png[pos..]) as usize;
+            let body = &png[pos + 4..pos + 8 + len];
+            assert_eq!(get_u32(&png[pos + 8 + len..]), crc32(body));
+            chunks.push((body[..4].to_vec(), body[4..].to_vec()));
+            pos += 12 + len;
+        }
+        let kinds: Vec<&[u8]> = chunks.iter().map(|chunk| &chunk.0[..]).collect();
+        assert_eq!(kinds, vec![&b"IHDR"[..], &b"IDAT"[..], &b"IEND"[..]]);
+
+        let header = &chunks[0].1;
+        let (width, height) = (get_u32(header), get_u32(&header[4..]));
+        assert_eq!(&header[8..], &[8, 6, 0, 0, 0]);
+
+        let zlib = &chunks[1].1;
+        assert_eq!((zlib[0] as u16 * 256 + zlib[1] as u16) % 31, 0);
+        let mut pos = 2;
+        let mut scanlines = Vec::new();
+        loop {
+            let last = zlib[pos];
+            let len = zlib[pos + 1] as usize | (zlib[pos + 2] as usize) << 8;
+            let nlen = zlib[pos + 3] as usize | (zlib[pos + 4] as usize) << 8;
+            assert_eq!(len ^ nlen, 0xFFFF);
+            scanlines.extend_from_slice(&zlib[pos + 5..pos + 5 + len]);
+            pos += 5 + len;
+            if last == 1 {
+                break;
+            }
+        }
+        assert_eq!(get_u32(&zlib[pos..]), adler32(&scanlines));
+        assert_eq!(pos + 4, zlib.len());
+
+        let stride = width as usize * 4 + 1;
+        let mut rgba = Vec::new();
+        for line in scanlines.chunks(stride) {
+            assert_eq!(line[0], 0);
+            rgba.extend_from_slice(&line[1..]);
+        }
+        (width, height, rgba)
+    }
+
+    #[test]
+    fn checksums_match_known_values() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        // Long enough that the sums must be reduced along the way.
+        assert_eq!(adler32(&[0xFF; 100000]), 0x149A_302C);
+    }
+
+    #[test]
+    fn pixels_round_trip() {
+        let rgba: Vec<u8> = (0..3 * 2 * 4).map(|i| i as u8).collect();
+        assert_eq!(decode(&encode(3, 2, &rgba)), (3, 2, rgba));
+    }
+
+    #[test]
+    fn large_images_span_several_stored_blocks() {
+        let (width, height) = (200, 100);
+        let rgba: Vec<u8> = (0..width * height * 4).map(|i| (i * 7) as u8).collect();
+        assert_eq!(decode(&encode(width, height, &rgba)), (width, height, rgba));
+    }
+
+    #[test]
+    fn empty_images_still_have_image_data() {
+        assert_eq!(decode(&encode(0, 0, &[])), (0, 0, vec![]));
+    }
+}
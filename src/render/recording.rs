@@ -0,0 +1,167 @@
+use render::{BufferId, FramebufferId, LiveResources, Primitive, ProgramId, Renderer, Uniform};
+use std::collections::HashSet;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    CreateBuffer(BufferId, Vec<f32>),
+    CreateStreamBuffer(BufferId),
+    Stream(BufferId, Vec<f32>),
+    DeleteBuffer(BufferId),
+    CreateProgram(ProgramId),
+    DeleteProgram(ProgramId),
+    UseProgram(ProgramId),
+    Uniform(ProgramId, String, Uniform),
+    Clear(f32, f32, f32, f32),
+    Draw(BufferId, Primitive, i32, i32),
+    CreateFramebuffer(FramebufferId, u32, u32),
+    DeleteFramebuffer(FramebufferId),
+    BindFramebuffer(Option<FramebufferId>),
+    ReadPixels(u32, u32),
+}
+
+// Draws nothing and remembers every call, in order. Ids are handed out from
+// 1 upwards, buffers, programs and framebuffers counted separately. Every buffer stands
+// for a vertex array and its buffer, every program for two shaders as well.
+pub struct RecordingRenderer {
+    commands: Vec<Command>,
+    next_buffer: u32,
+    next_program: u32,
+    next_framebuffer: u32,
+    buffers: HashSet<BufferId>,
+    programs: HashSet<ProgramId>,
+    framebuffers: HashSet<FramebufferId>,
+}
+
+impl RecordingRenderer {
+    pub fn new() -> RecordingRenderer {
+        RecordingRenderer {
+            commands: Vec::new(),
+            next_buffer: 1,
+            next_program: 1,
+            next_framebuffer: 1,
+            buffers: HashSet::new(),
+            programs: HashSet::new(),
+            framebuffers: HashSet::new(),
+        }
+    }
+
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    // Hands over what was recorded so far, as at the end of a frame.
+    pub fn take_commands(&mut self) -> Vec<Command> {
+        self.commands.split_off(0)
+    }
+
+    // The vertices a buffer was created with or, for a stream buffer, last
+    // streamed into it.
+    pub fn vertices(&self, buffer: BufferId) -> Option<&[f32]> {
+        self.commands
+            .iter()
+            .rev()
+            .filter_map(|command| match *command {
+                Command::CreateBuffer(id, ref vertices) | Command::Stream(id, ref vertices)
+                    if id == buffer =>
+                {
+                    Some(&vertices[..])
+                }
+                _ => None,
+            })
+            .next()
+    }
+}
+
+impl Renderer for RecordingRenderer {
+    fn create_buffer(&mut self, vertices: &[f32]) -> BufferId {
+        let id = BufferId(self.next_buffer);
+        self.next_buffer += 1;
+        self.buffers.insert(id);
+        self.commands
+            .push(Command::CreateBuffer(id, vertices.to_vec()));
+        id
+    }
+
+    fn create_stream_buffer(&mut self) -> BufferId {
+        let id = BufferId(self.next_buffer);
+        self.next_buffer += 1;
+        self.buffers.insert(id);
+        self.commands.push(Command::CreateStreamBuffer(id));
+        id
+    }
+
+    fn stream(&mut self, buffer: BufferId, vertices: &[f32]) {
+        self.commands
+            .push(Command::Stream(buffer, vertices.to_vec()));
+    }
+
+    fn delete_buffer(&mut self, buffer: BufferId) {
+        self.buffers.remove(&buffer);
+        self.commands.push(Command::DeleteBuffer(buffer));
+    }
+
+    fn create_program(&mut self, _vertex: &str, _fragment: &str) -> ProgramId {
+        let id = ProgramId(self.next_program);
+        self.next_program += 1;
+        self.programs.insert(id);
+        self.commands.push(Command::CreateProgram(id));
+        id
+    }
+
+    fn delete_program(&mut self, program: ProgramId) {
+        self.programs.remove(&program);
+        self.commands.push(Command::DeleteProgram(program));
+    }
+
+    fn use_program(&mut self, program: ProgramId) {
+        self.commands.push(Command::UseProgram(program));
+    }
+
+    fn uniform(&mut self, program: ProgramId, name: &str, value: Uniform) {
+        self.commands
+            .push(Command::Uniform(program, name.to_string(), value));
+    }
+
+    fn clear(&mut self, red: f32, green: f32, blue: f32, alpha: f32) {
+        self.commands.push(Command::Clear(red, green, blue, alpha));
+    }
+
+    fn draw(&mut self, buffer: BufferId, primitive: Primitive, first: i32, count: i32) {
+        self.commands
+            .push(Command::Draw(buffer, primitive, first, count));
+    }
+
+    fn create_framebuffer(&mut self, width: u32, height: u32) -> FramebufferId {
+        let id = FramebufferId(self.next_framebuffer);
+        self.next_framebuffer += 1;
+        self.framebuffers.insert(id);
+        self.commands
+            .push(Command::CreateFramebuffer(id, width, height));
+        id
+    }
+
+    fn delete_framebuffer(&mut self, framebuffer: FramebufferId) {
+        self.framebuffers.remove(&framebuffer);
+        self.commands.push(Command::DeleteFramebuffer(framebuffer));
+    }
+
+    fn bind_framebuffer(&mut self, framebuffer: Option<FramebufferId>) {
+        self.commands.push(Command::BindFramebuffer(framebuffer));
+    }
+
+    // Nothing was drawn, so every pixel is transparent black.
+    fn read_pixels(&mut self, width: u32, height: u32) -> Vec<u8> {
+        self.commands.push(Command::ReadPixels(width, height));
+        vec![0; width as usize * height as usize * 4]
+    }
+
+    fn live(&self) -> LiveResources {
+        LiveResources {
+            vertex_arrays: self.buffers.len(),
+            buffers: self.buffers.len(),
+            shaders: self.programs.len() * 2,
+            programs: self.programs.len(),
+            framebuffers: self.framebuffers.len(),
+        }
+    }
+}
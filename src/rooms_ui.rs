@@ -1,76 +1,156 @@
+use cgmath::{Matrix4, Point2, Vector3};
 use collision::{Aabb2, Contains};
-use cgmath::Point2;
-use graphics::Gfx;
-use gfx_gl::*;
+use network::RoomInfo;
+use render::batch::Colour;
+use render::{Batch, ProgramId};
+
+// The lobby lays rooms out as a grid of fixed-size tiles, left to right and
+// top to bottom, as many columns as fit the window. Tile coordinates are
+// window pixels with y pointing up, before scrolling.
+const VIEW_WIDTH: i32 = 600;
+const VIEW_HEIGHT: i32 = 400;
+const TILE_SIZE: i32 = 100;
+const TILE_GAP: i32 = 10;
+const COLUMNS: i32 = (VIEW_WIDTH - TILE_GAP) / (TILE_SIZE + TILE_GAP);
+const SCROLL_STEP: i32 = 30;
+const TILE_COLOUR: Colour = [1.0, 1.0, 0.4, 1.0];
+// Full rooms stay in the grid so the layout doesn't jump, just dimmed.
+const DIMMED_TILE_COLOUR: Colour = [0.4, 0.4, 0.16, 1.0];
 
 pub struct RoomUICollection {
     rooms: Vec<RoomUI>,
+    scroll: i32,
 }
 
 impl RoomUICollection {
-    pub fn new(count: u8) -> Box<RoomUICollection> {
-        let mut rooms: Vec<RoomUI> = Vec::with_capacity(4);
-        for i in 0..count {
-            let x: u32 = 10 + 100 * i as u32 + 10 * i as u32;
-            let y: u32 = 10;
-            let room = new_room_ui(i + 1, x, y, 100, 100);
-            rooms.push(room);
+    pub fn new() -> Box<RoomUICollection> {
+        Box::new(RoomUICollection {
+            rooms: Vec::new(),
+            scroll: 0,
+        })
+    }
+
+    // Replaces the catalogue, as when the server sends the full room list.
+    pub fn set_rooms(&mut self, infos: &[RoomInfo]) {
+        self.rooms = infos
+            .iter()
+            .map(|info| new_room_ui(info.clone(), 0, 0, TILE_SIZE, TILE_SIZE))
+            .collect();
+        self.layout();
+    }
+
+    pub fn add_room(&mut self, info: RoomInfo) {
+        let position = self.rooms.iter().position(|room| room.id() >= info.id);
+        let room = new_room_ui(info, 0, 0, TILE_SIZE, TILE_SIZE);
+        match position {
+            Some(i) if self.rooms[i].id() == room.id() => self.rooms[i] = room,
+            Some(i) => self.rooms.insert(i, room),
+            None => self.rooms.push(room),
+        }
+        self.layout();
+    }
+
+    pub fn remove_room(&mut self, id: u16) {
+        self.rooms.retain(|room| room.id() != id);
+        self.layout();
+    }
+
+    pub fn update_room(&mut self, info: RoomInfo) {
+        if let Some(room) = self.rooms.iter_mut().find(|room| room.id() == info.id) {
+            room.set_info(info);
+        }
+    }
+
+    // Puts every tile in its grid cell, keeping rooms in catalogue order.
+    fn layout(&mut self) {
+        for (i, room) in self.rooms.iter_mut().enumerate() {
+            let column = i as i32 % COLUMNS;
+            let row = i as i32 / COLUMNS;
+            let x = TILE_GAP + column * (TILE_SIZE + TILE_GAP);
+            let y = VIEW_HEIGHT - (row + 1) * (TILE_SIZE + TILE_GAP);
+            room.aabb = Aabb2::new(Point2::new(x, y), Point2::new(x + TILE_SIZE, y + TILE_SIZE));
         }
-        Box::new(RoomUICollection { rooms: rooms })
+        self.scroll_by(0);
     }
 
-    pub fn each(&self) -> ::std::slice::Iter<RoomUI> {
+    // How far the grid is scrolled up, in pixels.
+    pub fn scroll(&self) -> i32 {
+        self.scroll
+    }
+
+    // Positive steps scroll towards the first row, like a mouse wheel.
+    pub fn scroll_by(&mut self, steps: i32) {
+        let rows = (self.rooms.len() as i32 + COLUMNS - 1) / COLUMNS;
+        let height = rows * (TILE_SIZE + TILE_GAP) + TILE_GAP;
+        let max_scroll = ::std::cmp::max(0, height - VIEW_HEIGHT);
+        let scroll = ::std::cmp::max(0, self.scroll - steps * SCROLL_STEP);
+        self.scroll = ::std::cmp::min(scroll, max_scroll);
+    }
+
+    pub fn each<'a>(&'a self) -> ::std::slice::Iter<'a, RoomUI> {
         self.rooms.iter()
     }
 
-    pub fn each_mut(&mut self) -> ::std::slice::IterMut<RoomUI> {
+    pub fn each_mut<'a>(&'a mut self) -> ::std::slice::IterMut<'a, RoomUI> {
         self.rooms.iter_mut()
     }
 
-    pub fn find_by_coords(&self, x: u32, y: u32) -> Option<&RoomUI> {
+    // Queues every room, before scrolling.
+    pub fn draw(&self, batch: &mut Batch, material: ProgramId) {
+        for room in self.each() {
+            room.draw(batch, material);
+        }
+    }
+
+    // `x` and `y` are window pixels with y pointing up.
+    pub fn find_by_coords(&self, x: i32, y: i32) -> Option<&RoomUI> {
+        let y = y - self.scroll;
         self.each()
             .find(|&room| room.is_active() && room.contains(x, y))
     }
 }
 
 pub struct RoomUI {
-    number: u8,
+    info: RoomInfo,
     is_active: bool,
-    pub aabb: Aabb2<u32>,
-    pub gfx: Option<Gfx>,
+    pub aabb: Aabb2<i32>,
 }
 
 impl RoomUI {
-    pub fn contains(&self, x: u32, y: u32) -> bool {
+    pub fn contains(&self, x: i32, y: i32) -> bool {
         let p = Point2::new(x, y);
         self.aabb.contains(&p)
     }
 
-    pub fn calc_vertices(&self) -> Vec<f32> {
-        let vertices: Vec<f32> = self.aabb
-            .to_corners()
-            .iter()
-            .flat_map(|&point| vec![point.x.clone() as f32, point.y.clone() as f32, 1.0])
-            .collect();
-        vertices
-    }
-
-    pub fn draw(&self) {
-        match self.gfx {
-            Some(ref gfx) => unsafe {
-                gfx.gl.BindVertexArray(gfx.vao);
-                gfx.gl.DrawArrays(TRIANGLE_STRIP, 0, 4);
-            },
-
-            None => (),
-        }
+    pub fn draw(&self, batch: &mut Batch, material: ProgramId) {
+        let size = self.aabb.max - self.aabb.min;
+        let centre = self.aabb.min + size / 2;
+        let model = Matrix4::from_translation(Vector3::new(centre.x as f32, centre.y as f32, 0f32));
+        let colour = if self.is_active {
+            TILE_COLOUR
+        } else {
+            DIMMED_TILE_COLOUR
+        };
+        batch.quad(material, &model, size.x as f32, size.y as f32, colour);
     }
 
     pub fn is_active(&self) -> bool {
         self.is_active
     }
-    pub fn number(&self) -> u8 {
-        self.number
+    pub fn id(&self) -> u16 {
+        self.info.id
+    }
+    pub fn info(&self) -> &RoomInfo {
+        &self.info
+    }
+    // A room is active while the server has it open with a free place.
+    pub fn set_info(&mut self, info: RoomInfo) {
+        if info.is_full() {
+            self.deactivate();
+        } else {
+            self.activate();
+        }
+        self.info = info;
     }
     pub fn activate(&mut self) {
         self.is_active = true;
@@ -80,14 +160,165 @@ impl RoomUI {
     }
 }
 
-pub fn new_room_ui(number: u8, x: u32, y: u32, w: u32, h: u32) -> RoomUI {
+pub fn new_room_ui(info: RoomInfo, x: i32, y: i32, w: i32, h: i32) -> RoomUI {
     let min = Point2::new(x, y);
     let max = Point2::new(x + w, y + h);
     let aabb = Aabb2::new(min, max);
-    RoomUI {
-        number: number,
+    let mut room = RoomUI {
+        info: info.clone(),
         is_active: false,
         aabb: aabb,
-        gfx: None,
+    };
+    room.set_info(info);
+    room
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use render::{Command, Primitive, RecordingRenderer, RefRenderer};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn info(id: u16, members: u16) -> RoomInfo {
+        RoomInfo {
+            id: id,
+            name: format!("Room {}", id),
+            capacity: 4,
+            members: members,
+        }
+    }
+
+    fn catalogue(count: u16) -> Box<RoomUICollection> {
+        let mut rooms = RoomUICollection::new();
+        let infos: Vec<RoomInfo> = (1..count + 1).map(|id| info(id, 0)).collect();
+        rooms.set_rooms(&infos);
+        rooms
+    }
+
+    #[test]
+    fn tiles_fill_rows_from_the_top_left() {
+        let rooms = catalogue(6);
+        let corners: Vec<(i32, i32)> = rooms
+            .each()
+            .map(|room| (room.aabb.min.x, room.aabb.min.y))
+            .collect();
+        assert_eq!(
+            corners,
+            vec![
+                (10, 290),
+                (120, 290),
+                (230, 290),
+                (340, 290),
+                (450, 290),
+                (10, 180)
+            ]
+        );
+    }
+
+    #[test]
+    fn rooms_stay_in_id_order() {
+        let mut rooms = RoomUICollection::new();
+        rooms.add_room(info(3, 0));
+        rooms.add_room(info(1, 0));
+        rooms.add_room(info(2, 0));
+        rooms.add_room(info(2, 1));
+        rooms.remove_room(1);
+        let ids: Vec<u16> = rooms.each().map(|room| room.id()).collect();
+        assert_eq!(ids, vec![2, 3]);
+        assert_eq!(rooms.each().next().unwrap().info().members, 1);
+        assert_eq!(rooms.each().next().unwrap().aabb.min, Point2::new(10, 290));
+    }
+
+    #[test]
+    fn full_rooms_can_not_be_picked() {
+        let mut rooms = catalogue(2);
+        assert_eq!(rooms.find_by_coords(50, 300).map(|room| room.id()), Some(1));
+        assert!(rooms.find_by_coords(5, 300).is_none());
+
+        rooms.update_room(info(1, 4));
+        assert!(rooms.find_by_coords(50, 300).is_none());
+        rooms.update_room(info(1, 3));
+        assert_eq!(rooms.find_by_coords(50, 300).map(|room| room.id()), Some(1));
+    }
+
+    #[test]
+    fn scrolling_stops_at_both_ends() {
+        // Three rows fit the window, so there is nothing to scroll.
+        let mut rooms = catalogue(15);
+        rooms.scroll_by(-1);
+        assert_eq!(rooms.scroll(), 0);
+
+        // Four rows are 450 pixels high, 50 more than the window.
+        let mut rooms = catalogue(20);
+        rooms.scroll_by(1);
+        assert_eq!(rooms.scroll(), 0);
+        rooms.scroll_by(-1);
+        assert_eq!(rooms.scroll(), SCROLL_STEP);
+        rooms.scroll_by(-1);
+        assert_eq!(rooms.scroll(), 50);
+        rooms.scroll_by(1);
+        assert_eq!(rooms.scroll(), 50 - SCROLL_STEP);
+
+        // Shrinking the catalogue pulls the scroll back in range.
+        rooms.remove_room(20);
+        rooms.remove_room(19);
+        rooms.remove_room(18);
+        rooms.remove_room(17);
+        rooms.remove_room(16);
+        assert_eq!(rooms.scroll(), 0);
+    }
+
+    #[test]
+    fn picking_accounts_for_the_scroll() {
+        let mut rooms = catalogue(20);
+        // The last row spans window y -40 to 60 before scrolling.
+        assert_eq!(rooms.find_by_coords(50, 20).map(|room| room.id()), Some(16));
+        rooms.scroll_by(-2);
+        assert_eq!(rooms.find_by_coords(50, 20).map(|room| room.id()), Some(16));
+        assert_eq!(
+            rooms.find_by_coords(50, 100).map(|room| room.id()),
+            Some(16)
+        );
+        assert_eq!(
+            rooms.find_by_coords(50, 170).map(|room| room.id()),
+            Some(11)
+        );
+    }
+
+    #[test]
+    fn every_room_is_drawn_in_one_call() {
+        let recording = Rc::new(RefCell::new(RecordingRenderer::new()));
+        let renderer: RefRenderer = recording.clone();
+        let mut batch = Batch::new(&renderer);
+
+        let mut rooms = catalogue(3);
+        rooms.update_room(info(2, 4));
+        rooms.draw(&mut batch, ProgramId(7));
+        batch.flush(&Matrix4::from_scale(1f32));
+
+        let commands = recording.borrow_mut().take_commands();
+        let (buffer, vertices) = commands
+            .iter()
+            .filter_map(|command| match *command {
+                Command::Stream(id, ref vertices) => Some((id, vertices.clone())),
+                _ => None,
+            })
+            .next()
+            .unwrap();
+        assert_eq!(
+            commands.last(),
+            Some(&Command::Draw(buffer, Primitive::Triangles, 0, 18))
+        );
+
+        // Six vertices of six floats a tile, each starting at its bottom left
+        // corner with the colour right after. Room 2 is full, so it is dimmed.
+        assert_eq!(vertices.len(), 108);
+        assert_eq!(&vertices[..2], &[10f32, 290f32]);
+        assert_eq!(&vertices[2..6], &TILE_COLOUR);
+        assert_eq!(&vertices[36..38], &[120f32, 290f32]);
+        assert_eq!(&vertices[38..42], &DIMMED_TILE_COLOUR);
+        assert_eq!(&vertices[72..74], &[230f32, 290f32]);
+        assert_eq!(&vertices[74..78], &TILE_COLOUR);
     }
 }
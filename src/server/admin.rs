@@ -0,0 +1,187 @@
+use network::message::Message;
+use server::console::{ConsoleCommand, CONSOLE_DEFAULT, CONSOLE_GREEN, CONSOLE_RED};
+use server::{Server, DEFAULT_ROOM_CAPACITY};
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+use timers;
+
+impl Server {
+    pub fn run_command(&mut self, command: ConsoleCommand, colour: bool) -> String {
+        let mut out = String::new();
+        match command {
+            ConsoleCommand::CreateRoom(name) => {
+                let id = self.open_room(name, DEFAULT_ROOM_CAPACITY);
+                let _ = write!(out, "Spawn room {} {:?}", id, self.rooms[&id].name);
+            }
+
+            ConsoleCommand::CloseRoom(id) => {
+                if self.close_room(id) {
+                    let _ = write!(out, "Close room {}", id);
+                } else {
+                    let _ = write!(out, "No room {}", id);
+                }
+            }
+
+            ConsoleCommand::Status => out = self.status(colour),
+
+            ConsoleCommand::Clients => {
+                let now = Instant::now();
+                let _ = write!(out, "{} clients", self.peers.len());
+                for peer in self.peers.values() {
+                    let session = match peer.session {
+                        Some(session) => session.to_string(),
+                        None => "-".to_string(),
+                    };
+                    let room = match peer.room {
+                        Some(id) => id.to_string(),
+                        None => "-".to_string(),
+                    };
+                    let _ = write!(
+                        out,
+                        "\n{}\tid {}\tsession {}\tlistening {}\troom {}\tlast seen {:.1}s ago\tconnected {:.0}s",
+                        peer.addr,
+                        peer.id,
+                        session,
+                        peer.listening,
+                        room,
+                        seconds(now.duration_since(peer.last_seen)),
+                        seconds(now.duration_since(peer.connected_at))
+                    );
+                }
+            }
+
+            ConsoleCommand::Kick(addr) => {
+                if self.peers.contains_key(&addr) {
+                    // Sent once and unreliably: the peer is forgotten right
+                    // away, and if this is lost the client times out instead.
+                    self.send(
+                        addr,
+                        Message::Kicked("kicked by operator".to_string()),
+                        false,
+                    );
+                    self.leave_room(&addr);
+                    self.peers.remove(&addr);
+                    self.kicked.insert(addr, Instant::now());
+                    let _ = write!(out, "Kicked {}", addr);
+                } else {
+                    let _ = write!(out, "No client at {}", addr);
+                }
+            }
+
+            ConsoleCommand::Broadcast(text) => {
+                let addrs: Vec<_> = self.peers.keys().cloned().collect();
+                for addr in addrs.iter() {
+                    self.send(*addr, Message::Notice(text.clone()), true);
+                }
+                let _ = write!(out, "Sent to {} clients", addrs.len());
+            }
+
+            ConsoleCommand::Room(id) => match self.rooms.get(&id) {
+                Some(room) => {
+                    let _ = write!(
+                        out,
+                        "Room {} {:?}: {}/{} members",
+                        id,
+                        room.name,
+                        room.members.len(),
+                        room.capacity
+                    );
+                    for (addr, member) in room.members.iter() {
+                        let _ = write!(
+                            out,
+                            "\n{}\tid {}\tpos ({:.1}, {:.1})",
+                            addr, member.id, member.pos.x, member.pos.y
+                        );
+                        if member.moving {
+                            let _ = write!(
+                                out,
+                                "\tmoving ({:.2}, {:.2})",
+                                member.direction.x, member.direction.y
+                            );
+                        }
+                    }
+                }
+                None => {
+                    let _ = write!(out, "No room {}", id);
+                }
+            },
+
+            ConsoleCommand::TickRate(rate) => {
+                self.set_tick_rate(rate);
+                let _ = write!(out, "Tick rate {} Hz", rate);
+            }
+
+            ConsoleCommand::Stats => {
+                let stats = &self.stats;
+                let listeners = self.peers.values().filter(|peer| peer.listening).count();
+                let members: usize = self.rooms.values().map(|room| room.members.len()).sum();
+                let _ = write!(
+                    out,
+                    "Uptime {:.0}s, tick rate {} Hz\n\
+                     Clients {}, listening {}, rooms {}, members {}\n\
+                     In {} datagrams / {} bytes, out {} datagrams / {} bytes\n\
+                     Malformed {}, retransmits {}, undelivered {}",
+                    seconds(stats.started.elapsed()),
+                    self.tick_rate,
+                    self.peers.len(),
+                    listeners,
+                    self.rooms.len(),
+                    members,
+                    stats.datagrams_in,
+                    stats.bytes_in,
+                    stats.datagrams_out,
+                    stats.bytes_out,
+                    stats.malformed,
+                    stats.retransmits,
+                    stats.undelivered
+                );
+            }
+
+            ConsoleCommand::Exit => {
+                self.running = false;
+                out.push_str("Bye");
+            }
+        }
+        out
+    }
+
+    fn status(&self, colour: bool) -> String {
+        let (green, red, default) = if colour {
+            (CONSOLE_GREEN, CONSOLE_RED, CONSOLE_DEFAULT)
+        } else {
+            ("", "", "")
+        };
+
+        let mut out = String::new();
+        for room in self.rooms.values() {
+            let (state, state_colour) = if room.is_full() {
+                ("FULL", red)
+            } else {
+                ("OPEN", green)
+            };
+            let _ = writeln!(
+                out,
+                "Room {} {:?}: {}{}{} ({}/{} members)",
+                room.id,
+                room.name,
+                state_colour,
+                state,
+                default,
+                room.members.len(),
+                room.capacity
+            );
+        }
+        let listeners = self.peers.values().filter(|peer| peer.listening).count();
+        let _ = write!(
+            out,
+            "Clients: {}, listening: {}",
+            self.peers.len(),
+            listeners
+        );
+        out
+    }
+}
+
+fn seconds(duration: Duration) -> f32 {
+    timers::as_millis_f32(duration) / 1000f32
+}
@@ -0,0 +1,189 @@
+use config;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+pub const CONSOLE_DEFAULT: &'static str = "\x1B[0m";
+pub const CONSOLE_RED: &'static str = "\x1B[31m";
+pub const CONSOLE_GREEN: &'static str = "\x1B[32m";
+
+const HELP: &'static str = "Commands:\n\
+                            \tcreate room [name]\n\
+                            \tclose room [number]\n\
+                            \tstatus\n\
+                            \tclients\n\
+                            \tkick [address]\n\
+                            \tbroadcast [text]\n\
+                            \troom [number]\n\
+                            \ttick [rate]\n\
+                            \tstats\n\
+                            \texit";
+
+pub enum ConsoleCommand {
+    CreateRoom(Option<String>),
+    CloseRoom(u16),
+    Status,
+    Clients,
+    Kick(SocketAddr),
+    Broadcast(String),
+    Room(u16),
+    TickRate(u32),
+    Stats,
+    Exit,
+}
+
+// A command on its way to the server loop. The output comes back on `reply`
+// so that whoever typed the command gets to see it.
+pub struct Request {
+    pub command: ConsoleCommand,
+    pub colour: bool,
+    pub reply: Sender<String>,
+}
+
+pub fn help() {
+    println!("{}", HELP);
+}
+
+fn carriage() {
+    print!("-> ");
+    io::stdout().flush().unwrap();
+}
+
+pub fn parse(line: &str) -> Result<ConsoleCommand, String> {
+    let line = line.trim();
+    let mut split = line.splitn(2, char::is_whitespace);
+    let command = split.next().unwrap_or("");
+    let rest = split.next().unwrap_or("").trim();
+    let args: Vec<&str> = rest.split_whitespace().collect();
+
+    match (command, args.as_slice()) {
+        ("create", ["room", ..]) => {
+            let name = rest["room".len()..].trim();
+            let name = if name.is_empty() {
+                None
+            } else {
+                Some(name.to_string())
+            };
+            Ok(ConsoleCommand::CreateRoom(name))
+        }
+        ("close", ["room", number]) => parse_room(number).map(ConsoleCommand::CloseRoom),
+        ("status", []) => Ok(ConsoleCommand::Status),
+        ("clients", []) => Ok(ConsoleCommand::Clients),
+        ("kick", [addr]) => match addr.parse::<SocketAddr>() {
+            Ok(addr) => Ok(ConsoleCommand::Kick(addr)),
+            Err(_) => Err(format!("bad address {:?}", addr)),
+        },
+        ("broadcast", _) if !rest.is_empty() => Ok(ConsoleCommand::Broadcast(rest.to_string())),
+        ("room", [number]) => parse_room(number).map(ConsoleCommand::Room),
+        ("tick", [rate]) => config::parse_tick_rate(rate).map(ConsoleCommand::TickRate),
+        ("stats", []) => Ok(ConsoleCommand::Stats),
+        ("exit", []) => Ok(ConsoleCommand::Exit),
+        _ => Err(HELP.to_string()),
+    }
+}
+
+fn parse_room(number: &str) -> Result<u16, String> {
+    number
+        .parse::<u16>()
+        .map_err(|_| format!("bad room number {:?}", number))
+}
+
+// Commands arrive from stdin and, if `control_addr` is set, from a local TCP
+// socket that takes one command per line and answers with its output, e.g.
+// `echo stats | nc 127.0.0.1 45100`. Both run on their own threads so the
+// server loop never blocks on them.
+pub fn spawn(control_addr: Option<SocketAddr>) -> io::Result<Receiver<Request>> {
+    let (sender, receiver) = channel::<Request>();
+
+    if let Some(addr) = control_addr {
+        let listener = TcpListener::bind(addr)?;
+        println!("Control socket on {}", addr);
+        let sender = sender.clone();
+        thread::Builder::new()
+            .name("control".to_string())
+            .spawn(move || accept_control(listener, sender))?;
+    }
+
+    thread::Builder::new()
+        .name("console".to_string())
+        .spawn(move || read_stdin(sender))?;
+
+    Ok(receiver)
+}
+
+fn execute(sender: &Sender<Request>, command: ConsoleCommand, colour: bool) -> Option<String> {
+    let (reply, output) = channel::<String>();
+    let request = Request {
+        command: command,
+        colour: colour,
+        reply: reply,
+    };
+    if sender.send(request).is_err() {
+        return None;
+    }
+    output.recv().ok()
+}
+
+// Ends quietly when stdin closes, which leaves a server started in the
+// background running.
+fn read_stdin(sender: Sender<Request>) {
+    let stdin = io::stdin();
+    carriage();
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if !line.trim().is_empty() {
+            match parse(&line) {
+                Ok(command) => match execute(&sender, command, true) {
+                    Some(output) => println!("{}", output),
+                    None => break,
+                },
+                Err(message) => println!("{}", message),
+            }
+        }
+        carriage();
+    }
+}
+
+fn accept_control(listener: TcpListener, sender: Sender<Request>) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let sender = sender.clone();
+                let _ = thread::Builder::new()
+                    .name("control-client".to_string())
+                    .spawn(move || serve_control(stream, sender));
+            }
+            Err(e) => println!("Control socket error: {:?}", e),
+        }
+    }
+}
+
+fn serve_control(stream: TcpStream, sender: Sender<Request>) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let output = match parse(&line) {
+            Ok(command) => match execute(&sender, command, false) {
+                Some(output) => output,
+                None => break,
+            },
+            Err(message) => message,
+        };
+        if writeln!(writer, "{}", output).is_err() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,617 @@
+pub use self::console::{ConsoleCommand, Request};
+pub use self::peer::*;
+pub use self::room::*;
+pub use self::stats::*;
+
+mod admin;
+pub mod console;
+mod peer;
+mod room;
+mod stats;
+
+use config::ServerConfig;
+use network::message::{Message, RoomInfo};
+use network::wire::{Frame, FRAME_MARKER};
+use network::{MAX_DATAGRAM_SIZE, RECV_TIMEOUT_MS};
+use protocol::enums::MessageType;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+const ANNOUNCE_INTERVAL_MS: u64 = 1000;
+// Clients heartbeat every second, so this is several missed beats.
+const PEER_TIMEOUT_MS: u64 = 10000;
+// How long a kicked address is refused, so a client restarted by hand can
+// not walk straight back in.
+const KICK_BAN_MS: u64 = 60000;
+
+// Authoritative game server. Owns the socket, every connected peer and the
+// rooms, and advances member movement at a fixed tick rate.
+pub struct Server {
+    socket: UdpSocket,
+    peers: HashMap<SocketAddr, Peer>,
+    // When each recently kicked address was kicked.
+    kicked: HashMap<SocketAddr, Instant>,
+    rooms: BTreeMap<u16, Room>,
+    next_room_id: u16,
+    next_peer_id: u16,
+    console: Receiver<Request>,
+    stats: Stats,
+    tick_rate: u32,
+    tick: Duration,
+    last_tick: Instant,
+    last_announce: Instant,
+    recv_buffer: Vec<u8>,
+    running: bool,
+}
+
+impl Server {
+    pub fn bind(config: &ServerConfig, console: Receiver<Request>) -> io::Result<Server> {
+        println!("Binding {}", config.bind_addr);
+        let socket = UdpSocket::bind(config.bind_addr)?;
+        socket.set_read_timeout(Some(Duration::from_millis(RECV_TIMEOUT_MS)))?;
+
+        let now = Instant::now();
+
+        let mut server = Server {
+            socket: socket,
+            peers: HashMap::new(),
+            kicked: HashMap::new(),
+            rooms: BTreeMap::new(),
+            next_room_id: 1,
+            next_peer_id: 1,
+            console: console,
+            stats: Stats::new(now),
+            tick_rate: config.tick_rate,
+            tick: tick_duration(config.tick_rate),
+            last_tick: now,
+            last_announce: now,
+            recv_buffer: vec![0; MAX_DATAGRAM_SIZE + 1],
+            running: true,
+        };
+        server.open_room(None, DEFAULT_ROOM_CAPACITY);
+        Ok(server)
+    }
+
+    pub fn run(&mut self) {
+        while self.running {
+            self.handle_console();
+            self.recv();
+
+            let now = Instant::now();
+            self.retransmit(now);
+
+            let elapsed = now.duration_since(self.last_tick);
+            if elapsed >= self.tick {
+                self.last_tick = now;
+                for room in self.rooms.values_mut() {
+                    room.simulate(elapsed);
+                }
+                self.publish_state();
+            }
+
+            if now.duration_since(self.last_announce) >= Duration::from_millis(ANNOUNCE_INTERVAL_MS)
+            {
+                self.last_announce = now;
+                self.announce();
+            }
+
+            self.expire_peers(now);
+        }
+    }
+
+    pub fn set_tick_rate(&mut self, rate: u32) {
+        self.tick_rate = rate;
+        self.tick = tick_duration(rate);
+    }
+
+    fn handle_console(&mut self) {
+        while let Ok(request) = self.console.try_recv() {
+            let output = self.run_command(request.command, request.colour);
+            let _ = request.reply.send(output);
+        }
+    }
+
+    fn recv(&mut self) {
+        let (size, addr) = match self.socket.recv_from(&mut self.recv_buffer) {
+            Ok(received) => received,
+            Err(ref e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                return
+            }
+            Err(e) => {
+                println!("Network error: {:?}", e);
+                return;
+            }
+        };
+        self.stats.datagrams_in += 1;
+        self.stats.bytes_in += size as u64;
+        if size > MAX_DATAGRAM_SIZE {
+            self.stats.malformed += 1;
+            println!("Dropped oversize datagram from {:?}", addr);
+            return;
+        }
+
+        let now = Instant::now();
+        let frame = Frame::decode(&self.recv_buffer[..size]);
+
+        // Only a handshake or a bare `chunk_protocol` datagram introduces a
+        // peer. Anything else from an unknown address, including this
+        // crate's message extensions, belongs to a session that was kicked
+        // or expired, and goes unanswered so the client notices.
+        let introduces = match frame {
+            Some(Frame::Connect(..)) => true,
+            Some(Frame::Unreliable(ref payload)) => payload[0] != FRAME_MARKER,
+            _ => false,
+        };
+        if introduces && !self.peers.contains_key(&addr) {
+            if self.kicked.contains_key(&addr) {
+                return;
+            }
+            let id = self.next_peer_id;
+            self.next_peer_id = self.next_peer_id.wrapping_add(1);
+            self.peers.insert(addr, Peer::new(addr, id, now));
+        }
+        match self.peers.get_mut(&addr) {
+            Some(peer) => peer.last_seen = now,
+            None => return,
+        }
+
+        match frame {
+            Some(Frame::Connect(session)) => {
+                let restarted = self.peers.get_mut(&addr).unwrap().handshake(session, now);
+                if restarted {
+                    println!("Session {} from {:?}", session, addr);
+                    self.leave_room(&addr);
+                }
+                self.send_frame(addr, &Frame::Accept(session));
+            }
+            Some(Frame::Ping(nonce)) => self.send_frame(addr, &Frame::Pong(nonce)),
+            Some(Frame::Ack(seq)) => self.peers.get_mut(&addr).unwrap().reliable.ack(seq),
+            Some(Frame::Reliable(seq, payload)) => {
+                let delivered = {
+                    let peer = self.peers.get_mut(&addr).unwrap();
+                    if peer.session.is_none() {
+                        return;
+                    }
+                    let (ack, delivered) = peer.reliable.receive(seq, payload);
+                    send(&self.socket, &mut self.stats, addr, &ack);
+                    delivered
+                };
+                for payload in delivered.iter() {
+                    self.deliver(addr, payload);
+                }
+            }
+            Some(Frame::Unreliable(payload)) => self.deliver(addr, &payload),
+            Some(Frame::Accept(..)) | Some(Frame::Pong(..)) => (),
+            None => {
+                self.stats.malformed += 1;
+                println!("Dropped malformed frame from {:?}", addr);
+            }
+        }
+    }
+
+    fn deliver(&mut self, addr: SocketAddr, payload: &Vec<u8>) {
+        match Message::decode(payload) {
+            Some(msg) => self.handle_message(addr, msg),
+            None => {
+                self.stats.malformed += 1;
+                println!("Dropped unknown message from {:?}", addr);
+            }
+        }
+    }
+
+    fn handle_message(&mut self, addr: SocketAddr, msg: Message) {
+        match msg {
+            Message::Protocol(MessageType::AddToListenersRequest) => {
+                if let Some(peer) = self.peers.get_mut(&addr) {
+                    peer.listening = true;
+                }
+                self.send(addr, Message::Protocol(MessageType::ServerOn), false);
+                let rooms = self.room_list();
+                self.send(addr, Message::RoomList(rooms), true);
+            }
+
+            Message::ListRooms => {
+                let rooms = self.room_list();
+                self.send(addr, Message::RoomList(rooms), true);
+            }
+
+            Message::Protocol(MessageType::RemoveFromListeners) => {
+                self.leave_room(&addr);
+                self.peers.remove(&addr);
+                println!("{:?} disconnected", addr);
+            }
+
+            Message::Protocol(MessageType::MemberIn) => {
+                match self.rooms.values().find(|room| !room.is_full()) {
+                    Some(room) => {
+                        let id = room.id;
+                        self.join_room(addr, id);
+                    }
+                    None => println!("{:?} wants a room but none has space", addr),
+                }
+            }
+
+            Message::JoinRoom(id) => self.join_room(addr, id),
+
+            Message::Protocol(MessageType::MemberMove(dx, dy)) => {
+                if let Some(room) = self.room_of(&addr) {
+                    room.start_move(&addr, dx, dy);
+                }
+            }
+
+            Message::Protocol(MessageType::MemberStopMove) => {
+                if let Some(room) = self.room_of(&addr) {
+                    room.stop_move(&addr);
+                }
+            }
+
+            Message::Input(input) => {
+                if let Some(room) = self.room_of(&addr) {
+                    room.apply_input(&addr, &input, Instant::now());
+                }
+            }
+
+            Message::LeaveRoom => self.leave_room(&addr),
+
+            Message::Protocol(MessageType::ServerOn)
+            | Message::Notice(_)
+            | Message::Kicked(_)
+            | Message::RoomList(_)
+            | Message::RoomAdded(_)
+            | Message::RoomRemoved(_)
+            | Message::RoomUpdated(_)
+            | Message::RoomJoined { .. }
+            | Message::JoinRefused(..)
+            | Message::MemberJoined(_)
+            | Message::MemberLeft(_)
+            | Message::RoomState(_)
+            | Message::InputAck { .. } => {}
+        }
+    }
+
+    fn room_of(&mut self, addr: &SocketAddr) -> Option<&mut Room> {
+        let id = match self.peers.get(addr) {
+            Some(peer) => peer.room,
+            None => None,
+        };
+        match id {
+            Some(id) => self.rooms.get_mut(&id),
+            None => None,
+        }
+    }
+
+    fn join_room(&mut self, addr: SocketAddr, id: u16) {
+        self.leave_room(&addr);
+        let member = match self.peers.get(&addr) {
+            Some(peer) => peer.id,
+            None => return,
+        };
+
+        let joined = match self.rooms.get_mut(&id) {
+            Some(room) => {
+                if room.join(addr, member, Instant::now()) {
+                    Ok(room.info())
+                } else {
+                    Err("the room is full")
+                }
+            }
+            None => Err("the room is closed"),
+        };
+        let info = match joined {
+            Ok(info) => info,
+            Err(reason) => {
+                self.send(addr, Message::JoinRefused(id, reason.to_string()), true);
+                return;
+            }
+        };
+
+        if let Some(peer) = self.peers.get_mut(&addr) {
+            peer.room = Some(id);
+        }
+        let members = self.rooms[&id].member_ids();
+        let joined = Message::RoomJoined {
+            room: info,
+            member: member,
+            members: members,
+        };
+        self.send(addr, joined, true);
+        self.send_to_room(id, Message::MemberJoined(member), Some(addr), true);
+        self.room_changed(id);
+    }
+
+    fn leave_room(&mut self, addr: &SocketAddr) {
+        let left = match self.room_of(addr) {
+            Some(room) => {
+                let id = room.id;
+                room.leave(addr).map(|member| (id, member.id))
+            }
+            None => None,
+        };
+        if let Some(peer) = self.peers.get_mut(addr) {
+            peer.room = None;
+        }
+        if let Some((id, member)) = left {
+            self.send_to_room(id, Message::MemberLeft(member), None, true);
+            self.room_changed(id);
+        }
+    }
+
+    // Ids are never reused while the server runs, so a client can't confuse
+    // a new room with one that closed under it.
+    fn open_room(&mut self, name: Option<String>, capacity: u16) -> u16 {
+        let id = self.next_room_id;
+        self.next_room_id = self.next_room_id.wrapping_add(1);
+        let name = name.unwrap_or_else(|| format!("Room {}", id));
+        let room = Room::new(id, name, capacity);
+        self.send_to_listeners(Message::RoomAdded(room.info()));
+        self.rooms.insert(id, room);
+        id
+    }
+
+    fn close_room(&mut self, id: u16) -> bool {
+        match self.rooms.remove(&id) {
+            Some(room) => {
+                for addr in room.members.keys() {
+                    if let Some(peer) = self.peers.get_mut(addr) {
+                        peer.room = None;
+                    }
+                }
+                self.send_to_listeners(Message::RoomRemoved(id));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn room_changed(&mut self, id: u16) {
+        let info = match self.rooms.get(&id) {
+            Some(room) => room.info(),
+            None => return,
+        };
+        self.send_to_listeners(Message::RoomUpdated(info));
+    }
+
+    fn room_list(&self) -> Vec<RoomInfo> {
+        self.rooms.values().map(|room| room.info()).collect()
+    }
+
+    fn listeners(&self) -> Vec<SocketAddr> {
+        self.peers
+            .values()
+            .filter(|peer| peer.listening)
+            .map(|peer| peer.addr)
+            .collect()
+    }
+
+    fn announce(&mut self) {
+        for addr in self.listeners() {
+            self.send(addr, Message::Protocol(MessageType::ServerOn), false);
+        }
+    }
+
+    // Positions go out unreliably: a lost one is superseded by the next tick.
+    // Each member also hears which of its inputs the positions include.
+    fn publish_state(&mut self) {
+        let mut states = Vec::new();
+        let mut acks = Vec::new();
+        for room in self.rooms.values() {
+            if room.members.is_empty() {
+                continue;
+            }
+            states.push((room.id, room.state()));
+            for (addr, member) in room.members.iter() {
+                if let Some(seq) = member.last_input {
+                    acks.push((*addr, seq, member.pos));
+                }
+            }
+        }
+        for (id, state) in states {
+            self.send_to_room(id, Message::RoomState(state), None, false);
+        }
+        for (addr, seq, pos) in acks {
+            self.send(addr, Message::InputAck { seq: seq, pos: pos }, false);
+        }
+    }
+
+    fn send_to_room(&mut self, id: u16, msg: Message, except: Option<SocketAddr>, reliable: bool) {
+        let members: Vec<SocketAddr> = match self.rooms.get(&id) {
+            Some(room) => room
+                .members
+                .keys()
+                .filter(|&&addr| Some(addr) != except)
+                .cloned()
+                .collect(),
+            None => return,
+        };
+        let payload = msg.encode();
+        for addr in members {
+            self.send_payload(addr, payload.clone(), reliable);
+        }
+    }
+
+    fn send_to_listeners(&mut self, msg: Message) {
+        let payload = msg.encode();
+        for addr in self.listeners() {
+            self.send_payload(addr, payload.clone(), true);
+        }
+    }
+
+    fn send(&mut self, addr: SocketAddr, msg: Message, reliable: bool) {
+        self.send_payload(addr, msg.encode(), reliable);
+    }
+
+    fn send_payload(&mut self, addr: SocketAddr, payload: Vec<u8>, reliable: bool) {
+        let frame = match self.peers.get_mut(&addr) {
+            Some(ref mut peer) if reliable && peer.session.is_some() => {
+                peer.reliable.send(payload, Instant::now())
+            }
+            _ => Frame::Unreliable(payload),
+        };
+        self.send_frame(addr, &frame);
+    }
+
+    fn send_frame(&mut self, addr: SocketAddr, frame: &Frame) {
+        send(&self.socket, &mut self.stats, addr, frame);
+    }
+
+    // A peer that never acknowledged a reliable message can't receive any
+    // more of them, so it is dropped; the client starts a new session.
+    fn retransmit(&mut self, now: Instant) {
+        let mut failed = Vec::new();
+        for peer in self.peers.values_mut() {
+            match peer.reliable.retransmit(now) {
+                Ok(frames) => {
+                    for frame in frames.iter() {
+                        self.stats.retransmits += 1;
+                        send(&self.socket, &mut self.stats, peer.addr, frame);
+                    }
+                }
+                Err(seq) => {
+                    self.stats.undelivered += 1;
+                    println!(
+                        "Reliable message {} to {:?} was never acknowledged",
+                        seq, peer.addr
+                    );
+                    failed.push(peer.addr);
+                }
+            }
+        }
+        for addr in failed {
+            self.leave_room(&addr);
+            self.peers.remove(&addr);
+        }
+    }
+
+    fn expire_peers(&mut self, now: Instant) {
+        let timeout = Duration::from_millis(PEER_TIMEOUT_MS);
+        let expired: Vec<SocketAddr> = self
+            .peers
+            .values()
+            .filter(|peer| now.duration_since(peer.last_seen) >= timeout)
+            .map(|peer| peer.addr)
+            .collect();
+        for addr in expired {
+            println!("{:?} timed out", addr);
+            self.leave_room(&addr);
+            self.peers.remove(&addr);
+        }
+
+        let ban = Duration::from_millis(KICK_BAN_MS);
+        self.kicked
+            .retain(|_, kicked_at| now.duration_since(*kicked_at) < ban);
+    }
+}
+
+fn tick_duration(rate: u32) -> Duration {
+    Duration::from_millis(1000 / rate as u64)
+}
+
+fn send(socket: &UdpSocket, stats: &mut Stats, addr: SocketAddr, frame: &Frame) {
+    let buf = frame.encode();
+    match socket.send_to(&buf, addr) {
+        Ok(size) => {
+            stats.datagrams_out += 1;
+            stats.bytes_out += size as u64;
+        }
+        Err(e) => println!("Network error: {:?}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Vector2;
+    use network::message::Input;
+    use std::sync::mpsc;
+
+    fn server() -> Server {
+        let mut config = ServerConfig::default();
+        config.bind_addr = "127.0.0.1:0".parse().unwrap();
+        let (_, console) = mpsc::channel();
+        Server::bind(&config, console).unwrap()
+    }
+
+    fn client() -> UdpSocket {
+        UdpSocket::bind("127.0.0.1:0").unwrap()
+    }
+
+    // Sends `buf` to the server and waits until it has read it.
+    fn deliver(server: &mut Server, client: &UdpSocket, buf: &[u8]) {
+        let before = server.stats.datagrams_in;
+        client
+            .send_to(buf, server.socket.local_addr().unwrap())
+            .unwrap();
+        for _ in 0..200 {
+            server.recv();
+            if server.stats.datagrams_in > before {
+                return;
+            }
+        }
+        panic!("the server never received the datagram");
+    }
+
+    #[test]
+    fn handshake_admits_a_peer() {
+        let mut server = server();
+        let client = client();
+        deliver(&mut server, &client, &Frame::Connect(1).encode());
+
+        let peer = &server.peers[&client.local_addr().unwrap()];
+        assert_eq!(peer.session, Some(1));
+    }
+
+    #[test]
+    fn extensions_from_unknown_addresses_are_ignored() {
+        let mut server = server();
+        let client = client();
+        let input = Input {
+            seq: 0,
+            direction: Vector2::new(1.0, 0.0),
+            duration_ms: 16,
+        };
+        deliver(&mut server, &client, &Message::Input(input).encode());
+        deliver(&mut server, &client, &Message::JoinRoom(1).encode());
+        deliver(&mut server, &client, &Message::LeaveRoom.encode());
+        deliver(&mut server, &client, &Frame::Ping(0).encode());
+
+        assert!(server.peers.is_empty());
+    }
+
+    #[test]
+    fn closing_an_unknown_room_says_so() {
+        let mut server = server();
+        let id = *server.rooms.keys().next().unwrap();
+        let unknown = id + 1;
+
+        assert_eq!(
+            server.run_command(ConsoleCommand::CloseRoom(unknown), false),
+            format!("No room {}", unknown)
+        );
+        assert_eq!(
+            server.run_command(ConsoleCommand::CloseRoom(id), false),
+            format!("Close room {}", id)
+        );
+        assert!(server.rooms.is_empty());
+    }
+
+    #[test]
+    fn kicked_addresses_can_not_start_over() {
+        let mut server = server();
+        let client = client();
+        let addr = client.local_addr().unwrap();
+        deliver(&mut server, &client, &Frame::Connect(1).encode());
+
+        server.run_command(ConsoleCommand::Kick(addr), false);
+        deliver(&mut server, &client, &Frame::Connect(2).encode());
+        assert!(server.peers.is_empty());
+
+        server.expire_peers(Instant::now() + Duration::from_millis(KICK_BAN_MS));
+        deliver(&mut server, &client, &Frame::Connect(3).encode());
+        assert_eq!(server.peers[&addr].session, Some(3));
+    }
+}
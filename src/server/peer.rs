@@ -0,0 +1,44 @@
+use network::reliable::ReliableChannel;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+pub struct Peer {
+    pub addr: SocketAddr,
+    // Identifies the peer to other clients, who never learn its address.
+    pub id: u16,
+    // None for peers that send bare `chunk_protocol` datagrams without ever
+    // doing the handshake; they only get unreliable traffic.
+    pub session: Option<u16>,
+    pub reliable: ReliableChannel,
+    pub listening: bool,
+    pub room: Option<u16>,
+    pub connected_at: Instant,
+    pub last_seen: Instant,
+}
+
+impl Peer {
+    pub fn new(addr: SocketAddr, id: u16, now: Instant) -> Peer {
+        Peer {
+            addr: addr,
+            id: id,
+            session: None,
+            reliable: ReliableChannel::new(),
+            listening: false,
+            room: None,
+            connected_at: now,
+            last_seen: now,
+        }
+    }
+
+    // Returns true when `session` starts a new session rather than repeating
+    // the handshake of the current one.
+    pub fn handshake(&mut self, session: u16, now: Instant) -> bool {
+        if self.session == Some(session) {
+            return false;
+        }
+        self.session = Some(session);
+        self.reliable = ReliableChannel::new();
+        self.connected_at = now;
+        true
+    }
+}
@@ -0,0 +1,141 @@
+use cgmath::*;
+use movement;
+use network::message::{Input, MemberState, RoomInfo};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use timers;
+
+pub const DEFAULT_ROOM_CAPACITY: u16 = 8;
+
+pub struct Member {
+    pub id: u16,
+    pub pos: Point2<f32>,
+    pub direction: Vector2<f32>,
+    pub moving: bool,
+    // The newest `Input` applied, echoed back so the client can reconcile.
+    pub last_input: Option<u16>,
+    pub input_budget: movement::InputBudget,
+}
+
+impl Member {
+    pub fn new(id: u16, now: Instant) -> Member {
+        Member {
+            id: id,
+            pos: Point2::new(0f32, 0f32),
+            direction: Vector2::zero(),
+            moving: false,
+            last_input: None,
+            input_budget: movement::InputBudget::new(now),
+        }
+    }
+}
+
+pub struct Room {
+    pub id: u16,
+    pub name: String,
+    pub capacity: u16,
+    pub members: HashMap<SocketAddr, Member>,
+}
+
+impl Room {
+    pub fn new(id: u16, name: String, capacity: u16) -> Room {
+        Room {
+            id: id,
+            name: name,
+            capacity: capacity,
+            members: HashMap::new(),
+        }
+    }
+
+    pub fn info(&self) -> RoomInfo {
+        RoomInfo {
+            id: self.id,
+            name: self.name.clone(),
+            capacity: self.capacity,
+            members: self.members.len() as u16,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.members.len() >= self.capacity as usize
+    }
+
+    // Returns false when the room is full.
+    pub fn join(&mut self, addr: SocketAddr, id: u16, now: Instant) -> bool {
+        if !self.members.contains_key(&addr) && self.is_full() {
+            return false;
+        }
+        self.members
+            .entry(addr)
+            .or_insert_with(|| Member::new(id, now));
+        true
+    }
+
+    pub fn state(&self) -> Vec<MemberState> {
+        self.members
+            .values()
+            .map(|member| MemberState {
+                id: member.id,
+                pos: member.pos,
+            })
+            .collect()
+    }
+
+    pub fn member_ids(&self) -> Vec<u16> {
+        let mut ids: Vec<u16> = self.members.values().map(|member| member.id).collect();
+        ids.sort();
+        ids
+    }
+
+    pub fn leave(&mut self, addr: &SocketAddr) -> Option<Member> {
+        self.members.remove(addr)
+    }
+
+    pub fn start_move(&mut self, addr: &SocketAddr, dx: f32, dy: f32) {
+        if let Some(member) = self.members.get_mut(addr) {
+            if let Some(direction) = movement::direction(dx, dy) {
+                member.direction = direction;
+                member.moving = true;
+            }
+        }
+    }
+
+    // Inputs are applied as they arrive rather than on the tick, exactly as
+    // the client predicted them. Late or repeated ones are dropped, and ones
+    // beyond the member's input budget are cut short; the client reconciles
+    // whatever movement they lost.
+    pub fn apply_input(&mut self, addr: &SocketAddr, input: &Input, now: Instant) {
+        if let Some(member) = self.members.get_mut(addr) {
+            let newer = match member.last_input {
+                Some(last) => movement::is_newer(input.seq, last),
+                None => true,
+            };
+            if newer {
+                let requested = ::std::cmp::min(input.duration_ms, movement::MAX_INPUT_MS);
+                let granted = Input {
+                    duration_ms: member.input_budget.grant(requested, now),
+                    ..input.clone()
+                };
+                member.moving = false;
+                member.pos = movement::apply(member.pos, &granted);
+                member.last_input = Some(input.seq);
+            }
+        }
+    }
+
+    pub fn stop_move(&mut self, addr: &SocketAddr) {
+        if let Some(member) = self.members.get_mut(addr) {
+            member.moving = false;
+        }
+    }
+
+    pub fn simulate(&mut self, dt: Duration) {
+        let ms = timers::as_millis_f32(dt);
+        for member in self.members.values_mut() {
+            if member.moving {
+                member.pos = movement::step(member.pos, member.direction, ms);
+            }
+        }
+    }
+}
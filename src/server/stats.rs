@@ -0,0 +1,27 @@
+use std::time::Instant;
+
+pub struct Stats {
+    pub started: Instant,
+    pub datagrams_in: u64,
+    pub datagrams_out: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub malformed: u64,
+    pub retransmits: u64,
+    pub undelivered: u64,
+}
+
+impl Stats {
+    pub fn new(now: Instant) -> Stats {
+        Stats {
+            started: now,
+            datagrams_in: 0,
+            datagrams_out: 0,
+            bytes_in: 0,
+            bytes_out: 0,
+            malformed: 0,
+            retransmits: 0,
+            undelivered: 0,
+        }
+    }
+}
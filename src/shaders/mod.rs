@@ -1,125 +1,76 @@
-use gfx_gl::*;
 use cgmath::*;
-use std::ffi::CString;
+use rust_chunk::render::{ProgramId, RefRenderer, Uniform};
 
 pub mod smpl;
 
 pub struct Shader {
-    pub id: u32,
-    pub vs: u32,
-    pub fs: u32,
-    gl: Box<Gl>,
+    program: Option<ProgramId>,
+    vertex: String,
+    fragment: String,
+    renderer: RefRenderer,
 }
 
-pub fn new(gl: &Gl) -> Box<Shader> {
+pub fn new(renderer: &RefRenderer) -> Box<Shader> {
     Box::new(Shader {
-        id: unsafe { gl.CreateProgram() },
-        gl: Box::new(gl.clone()),
-        vs: 0,
-        fs: 0,
+        program: None,
+        vertex: String::new(),
+        fragment: String::new(),
+        renderer: renderer.clone(),
     })
 }
 
-fn shader_log(gl: &Gl, id: &u32) {
-    unsafe {
-        let mut compile_status: i32 = 0;
-        gl.GetShaderiv(*id, COMPILE_STATUS, &mut compile_status);
-        println!("Compile status: {:?}", compile_status > 0);
-        let mut log: Vec<i8> = vec![0; 512];
-        let log_ptr: *mut i8 = &mut log[0];
-        let length: *mut i32 = &mut 0;
-        gl.GetShaderInfoLog(*id, 512, length, log_ptr);
-        println!("Log length: {:?}", *length);
-        let s = String::from_raw_parts(log_ptr as *mut u8, *length as usize, 512);
-        println!("Log: {:?}", s);
-        println!("isShader: {:?} -> {:?}", *id, gl.IsShader(*id) > 0);
-        println!();
-    }
-}
-
-fn build_shader(gl: &Gl, type_: u32, source: &[u8]) -> u32 {
-    unsafe {
-        let id = gl.CreateShader(type_);
-        gl.ShaderSource(
-            id,
-            1,
-            &(source.as_ptr() as *const i8),
-            &(source.len() as i32),
-        );
-        gl.CompileShader(id);
-        id
-    }
-}
-
 impl Shader {
-    pub fn vertex_shader(&mut self, source: &[u8]) -> &mut Shader {
-        println!("##### Vertex Shader #####");
-        self.vs = build_shader(&self.gl, VERTEX_SHADER, &source);
-        shader_log(&self.gl, &self.vs);
+    pub fn vertex_shader(&mut self, source: &str) -> &mut Shader {
+        self.vertex = source.to_string();
         self
     }
 
-    pub fn fragment_shader(&mut self, source: &[u8]) -> &mut Shader {
-        println!("##### Fragment Shader #####");
-        self.fs = build_shader(&self.gl, FRAGMENT_SHADER, &source);
-        shader_log(&self.gl, &self.fs);
+    pub fn fragment_shader(&mut self, source: &str) -> &mut Shader {
+        self.fragment = source.to_string();
         self
     }
 
-    pub fn link(&self) {
-        println!("##### Link Program #####");
-        unsafe {
-            self.gl.AttachShader(self.id, self.vs);
-            self.gl.AttachShader(self.id, self.fs);
-            self.gl.LinkProgram(self.id);
+    pub fn link(&mut self) {
+        let program = self.renderer
+            .borrow_mut()
+            .create_program(&self.vertex, &self.fragment);
+        self.program = Some(program);
+    }
 
-            let mut link_status: i32 = 0;
-            self.gl.GetProgramiv(self.id, LINK_STATUS, &mut link_status);
-            println!("Link status: {:?}", link_status > 0);
-            let mut log: Vec<i8> = vec![0; 512];
-            let log_ptr: *mut i8 = &mut log[0];
-            let length: *mut i32 = &mut 0;
-            self.gl.GetProgramInfoLog(self.id, 512, length, log_ptr);
-            println!("Log length: {:?}", *length);
-            let s = String::from_raw_parts(log_ptr as *mut u8, *length as usize, 512);
-            println!("Log: {:?}", s);
-            println!();
-        }
+    // The linked program, as a `Batch` material.
+    pub fn program(&self) -> ProgramId {
+        self.program.expect("shader used before link")
     }
 
     pub fn use_program(&self) {
-        unsafe {
-            self.gl.UseProgram(self.id);
+        if let Some(program) = self.program {
+            self.renderer.borrow_mut().use_program(program);
         }
     }
 
-    pub fn get_uniform_location(&self, name: &str) -> i32 {
-        let uniform_name = CString::new(name).unwrap();
-        unsafe {
-            self.gl
-                .GetUniformLocation(self.id, uniform_name.to_bytes().as_ptr() as *const i8)
+    fn uniform(&self, name: &str, value: Uniform) {
+        if let Some(program) = self.program {
+            self.renderer.borrow_mut().uniform(program, name, value);
         }
     }
 
     pub fn uniform1f(&self, name: &str, val: f32) {
-        unsafe {
-            let location = self.get_uniform_location(name);
-            self.gl.Uniform1f(location, val);
-        }
+        self.uniform(name, Uniform::Float(val));
     }
 
     pub fn uniform2fv(&self, name: &str, vector: &Vector2<f32>) {
-        unsafe {
-            let location = self.get_uniform_location(name);
-            self.gl.Uniform2fv(location, 1, vector.as_ptr());
-        }
+        self.uniform(name, Uniform::Vec2(*vector));
     }
 
     pub fn uniform_matrix4fv(&self, name: &str, matrix: &Matrix4<f32>) {
-        unsafe {
-            let location = self.get_uniform_location(name);
-            self.gl
-                .UniformMatrix4fv(location, 1, FALSE, matrix.as_ptr());
+        self.uniform(name, Uniform::Mat4(*matrix));
+    }
+}
+
+impl Drop for Shader {
+    fn drop(&mut self) {
+        if let Some(program) = self.program {
+            self.renderer.borrow_mut().delete_program(program);
         }
     }
 }
@@ -10,27 +10,46 @@ pub const DEFAULT_VERTEX: &'static str = "
     }
 ";
 
-pub const YELLOW_FRAGMENT: &'static str = "
+pub const BACKGROUND_FRAGMENT: &'static str = "
     #version 410 core
 
     out vec4 out_color;
 
+    uniform float time;
+
     void main()
     {
-        out_color = vec4(1.0, 1.0, 0.4, 1.0);
+        vec2 uv = gl_FragCoord.xy / vec2(600.0, 400.0);
+        out_color = vec4(uv.x / 2.0 + 0.5, uv.y / 2.0 + 0.5, abs(sin(time)), 1.0);
     }
 ";
 
-pub const BACKGROUND_FRAGMENT: &'static str = "
+// For `Batch`: vertices arrive already placed and coloured.
+pub const BATCH_VERTEX: &'static str = "
     #version 410 core
+    layout(location=0) in vec2 pos;
+    layout(location=1) in vec4 colour;
 
-    out vec4 out_color;
+    uniform mat4 supermatrix;
 
-    uniform float time;
+    out vec4 vertex_colour;
 
     void main()
     {
-        vec2 uv = gl_FragCoord.xy / vec2(600.0, 400.0);
-        out_color = vec4(uv.x / 2.0 + 0.5, uv.y / 2.0 + 0.5, abs(sin(time)), 1.0);
+        vertex_colour = colour;
+        gl_Position = supermatrix * vec4(pos, 0.0, 1.0);
+    }
+";
+
+pub const BATCH_FRAGMENT: &'static str = "
+    #version 410 core
+
+    in vec4 vertex_colour;
+
+    out vec4 out_color;
+
+    void main()
+    {
+        out_color = vertex_colour;
     }
 ";
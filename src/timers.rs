@@ -1,26 +1,448 @@
-use time::SteadyTime;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
+// Game time. It starts at zero, runs at `scale` times real time and stands
+// still while paused. Stopwatches and scheduled callbacks are measured in
+// game time, so they pause and scale along with it.
 pub struct Timer {
-    start: SteadyTime,
-    last_frame: SteadyTime,
+    // Real instant and game time at the last pause, resume or scale change.
+    // Game time since then follows from how much real time has passed.
+    anchor: Instant,
+    base: Duration,
+    scale: f64,
+    paused: bool,
+    stopwatches: HashMap<String, Duration>,
+    scheduled: Vec<Scheduled>,
+    next_schedule_id: u32,
 }
 
+struct Scheduled {
+    id: u32,
+    due: Duration,
+    callback: Box<dyn FnOnce()>,
+}
+
+// One clock for the render loop and the scenes alike.
+pub type RefTimer = Rc<RefCell<Timer>>;
+
 pub fn new() -> Box<Timer> {
     Box::new(Timer {
-        start: SteadyTime::now(),
-        last_frame: SteadyTime::now(),
+        anchor: Instant::now(),
+        base: Duration::new(0, 0),
+        scale: 1.0,
+        paused: false,
+        stopwatches: HashMap::new(),
+        scheduled: Vec::new(),
+        next_schedule_id: 0,
     })
 }
 
+pub fn shared() -> RefTimer {
+    Rc::new(RefCell::new(*new()))
+}
+
+// A clock paused at zero, for runs that must come out the same every time.
+pub fn stopped() -> RefTimer {
+    let clock = shared();
+    {
+        let mut timer = clock.borrow_mut();
+        timer.base = Duration::new(0, 0);
+        timer.paused = true;
+    }
+    clock
+}
+
 impl Timer {
-    pub fn elapsed(&self) -> i64 {
-        let dur = SteadyTime::now() - self.start;
-        dur.num_milliseconds()
+    pub fn elapsed(&self) -> Duration {
+        if self.paused {
+            self.base
+        } else {
+            self.base + scaled(self.anchor.elapsed(), self.scale)
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.rebase();
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.rebase();
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    // Below 1 is slow motion, above 1 fast-forward. Negative scales are
+    // taken as 0: game time never runs backwards.
+    pub fn set_scale(&mut self, scale: f64) {
+        self.rebase();
+        self.scale = if scale > 0.0 { scale } else { 0.0 };
+    }
+
+    fn rebase(&mut self) {
+        self.base = self.elapsed();
+        self.anchor = Instant::now();
+    }
+
+    // Starting a stopwatch that is already running restarts it.
+    pub fn start_stopwatch(&mut self, name: &str) {
+        let now = self.elapsed();
+        self.stopwatches.insert(name.to_string(), now);
+    }
+
+    // Time on a running stopwatch.
+    pub fn stopwatch(&self, name: &str) -> Option<Duration> {
+        self.stopwatches
+            .get(name)
+            .map(|&started| self.elapsed() - started)
+    }
+
+    pub fn stop_stopwatch(&mut self, name: &str) -> Option<Duration> {
+        let lap = self.stopwatch(name);
+        self.stopwatches.remove(name);
+        lap
+    }
+
+    // Hands `callback` out from the first `take_due` at least `delay` of
+    // game time from now. The returned id cancels it.
+    pub fn schedule<F>(&mut self, delay: Duration, callback: F) -> u32
+    where
+        F: FnOnce() + 'static,
+    {
+        let id = self.next_schedule_id;
+        self.next_schedule_id = self.next_schedule_id.wrapping_add(1);
+        let due = self.elapsed() + delay;
+        self.scheduled.push(Scheduled {
+            id: id,
+            due: due,
+            callback: Box::new(callback),
+        });
+        id
+    }
+
+    // Returns false if the callback already ran or was cancelled.
+    pub fn cancel(&mut self, id: u32) -> bool {
+        let before = self.scheduled.len();
+        self.scheduled.retain(|scheduled| scheduled.id != id);
+        self.scheduled.len() != before
+    }
+
+    // Removes every callback that has come due and returns them earliest
+    // first. The caller runs them once it has let go of the timer, so a
+    // callback is free to use the clock, for example to schedule itself
+    // again.
+    pub fn take_due(&mut self) -> Vec<Box<dyn FnOnce()>> {
+        let now = self.elapsed();
+        let (mut due, pending): (Vec<Scheduled>, Vec<Scheduled>) = self
+            .scheduled
+            .drain(..)
+            .partition(|scheduled| scheduled.due <= now);
+        self.scheduled = pending;
+
+        due.sort_by_key(|scheduled| (scheduled.due, scheduled.id));
+        due.into_iter()
+            .map(|scheduled| scheduled.callback)
+            .collect()
+    }
+}
+
+// Longest frame the simulation catches up on. After a stall (a dragged
+// window, a breakpoint) the rest is dropped instead of replayed in a burst.
+const MAX_FRAME_MS: u64 = 250;
+
+// Hands game time to the simulation in fixed steps. Whatever is left over
+// after the last whole step becomes the render interpolation alpha.
+pub struct FixedStep {
+    step: Duration,
+    accumulator: Duration,
+    last: Duration,
+}
+
+impl FixedStep {
+    pub fn new(rate: u32) -> FixedStep {
+        FixedStep {
+            step: Duration::new(0, 1_000_000_000 / rate),
+            accumulator: Duration::new(0, 0),
+            last: Duration::new(0, 0),
+        }
+    }
+
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    // Adds the game time since the previous call and returns how many steps
+    // are due. `now` is `Timer::elapsed` of the clock driving the simulation.
+    pub fn advance(&mut self, now: Duration) -> u32 {
+        let frame = if now > self.last {
+            now - self.last
+        } else {
+            Duration::new(0, 0)
+        };
+        self.last = now;
+        self.accumulator += ::std::cmp::min(frame, Duration::from_millis(MAX_FRAME_MS));
+
+        let mut steps = 0;
+        while self.accumulator >= self.step {
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+        steps
+    }
+
+    // How far into the next step the present is, from 0 to 1.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_nanos() as f32 / self.step.as_nanos() as f32
+    }
+}
+
+// Real durations of the most recent frames, newest last.
+pub struct FrameTimes {
+    frames: VecDeque<Duration>,
+    capacity: usize,
+    last: Instant,
+}
+
+impl FrameTimes {
+    pub fn new(capacity: usize) -> FrameTimes {
+        FrameTimes {
+            frames: VecDeque::with_capacity(capacity),
+            capacity: capacity,
+            last: Instant::now(),
+        }
+    }
+
+    // Marks the end of a frame and returns how long it took.
+    pub fn frame(&mut self) -> Duration {
+        let now = Instant::now();
+        let frame = now.duration_since(self.last);
+        self.last = now;
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(frame);
+        frame
+    }
+
+    pub fn last(&self) -> Option<Duration> {
+        self.frames.back().cloned()
+    }
+
+    pub fn average(&self) -> Duration {
+        if self.frames.is_empty() {
+            return Duration::new(0, 0);
+        }
+        let total: u128 = self.frames.iter().map(|frame| frame.as_nanos()).sum();
+        Duration::from_nanos((total / self.frames.len() as u128) as u64)
+    }
+
+    pub fn longest(&self) -> Duration {
+        self.frames
+            .iter()
+            .cloned()
+            .max()
+            .unwrap_or_else(|| Duration::new(0, 0))
+    }
+
+    // Frames per second over the history, 0 before the first frame.
+    pub fn fps(&self) -> f32 {
+        let average = self.average().as_nanos();
+        if average == 0 {
+            0.0
+        } else {
+            1_000_000_000f32 / average as f32
+        }
+    }
+}
+
+pub fn as_millis_f32(duration: Duration) -> f32 {
+    duration.as_nanos() as f32 / 1_000_000f32
+}
+
+fn scaled(duration: Duration, scale: f64) -> Duration {
+    Duration::from_nanos((duration.as_nanos() as f64 * scale) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Lets `real` pass on the timer's clock without waiting for it.
+    fn pass(timer: &mut Timer, real: Duration) {
+        timer.anchor -= real;
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    // Real time also passes while the test runs, so allow a little slack.
+    fn assert_near(actual: Duration, expected: Duration) {
+        let slack = ms(20);
+        assert!(
+            actual >= expected && actual < expected + slack,
+            "{:?} is not close to {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn pause_and_resume_keep_game_time_continuous() {
+        let mut timer = *new();
+        pass(&mut timer, ms(100));
+        let before = timer.elapsed();
+        timer.pause();
+        let paused = timer.elapsed();
+        assert!(paused >= before);
+        assert_near(paused, ms(100));
+
+        pass(&mut timer, ms(500));
+        assert_eq!(timer.elapsed(), paused);
+
+        timer.resume();
+        assert_near(timer.elapsed(), paused);
+        pass(&mut timer, ms(50));
+        assert_near(timer.elapsed(), paused + ms(50));
+    }
+
+    #[test]
+    fn scale_changes_apply_from_now_on() {
+        let mut timer = *new();
+        pass(&mut timer, ms(100));
+        let before = timer.elapsed();
+        timer.set_scale(2.0);
+        assert!(timer.elapsed() >= before);
+        assert_near(timer.elapsed(), ms(100));
+
+        pass(&mut timer, ms(100));
+        assert_near(timer.elapsed(), ms(300));
+
+        timer.set_scale(-1.0);
+        assert_eq!(timer.scale(), 0.0);
+        let stopped = timer.elapsed();
+        pass(&mut timer, ms(100));
+        assert_eq!(timer.elapsed(), stopped);
+    }
+
+    #[test]
+    fn stopped_clocks_stay_at_zero() {
+        let clock = stopped();
+        pass(&mut clock.borrow_mut(), ms(100));
+        assert_eq!(clock.borrow().elapsed(), Duration::new(0, 0));
+    }
+
+    #[test]
+    fn stopwatches_run_on_game_time() {
+        let mut timer = *new();
+        timer.start_stopwatch("lap");
+        pass(&mut timer, ms(100));
+        timer.pause();
+        pass(&mut timer, ms(100));
+        assert_near(timer.stop_stopwatch("lap").unwrap(), ms(100));
+        assert_eq!(timer.stopwatch("lap"), None);
+    }
+
+    #[test]
+    fn due_callbacks_come_out_in_order() {
+        let mut timer = *new();
+        let order = Rc::new(RefCell::new(Vec::new()));
+        let mut ids = Vec::new();
+        for &delay in [30, 10, 10, 100].iter() {
+            let order = order.clone();
+            let id = timer.next_schedule_id;
+            ids.push(timer.schedule(ms(delay), move || order.borrow_mut().push(id)));
+        }
+
+        pass(&mut timer, ms(50));
+        for callback in timer.take_due() {
+            callback();
+        }
+        assert_eq!(*order.borrow(), vec![ids[1], ids[2], ids[0]]);
+        assert!(timer.take_due().is_empty());
+
+        assert!(!timer.cancel(ids[0]));
+        assert!(timer.cancel(ids[3]));
+        assert!(!timer.cancel(ids[3]));
+        pass(&mut timer, ms(100));
+        assert!(timer.take_due().is_empty());
+    }
+
+    #[test]
+    fn callbacks_can_use_the_clock() {
+        let clock = shared();
+        let runs = Rc::new(RefCell::new(0));
+
+        let (again, counter) = (clock.clone(), runs.clone());
+        clock.borrow_mut().schedule(Duration::new(0, 0), move || {
+            *counter.borrow_mut() += 1;
+            let counter = counter.clone();
+            again
+                .borrow_mut()
+                .schedule(Duration::new(0, 0), move || *counter.borrow_mut() += 1);
+        });
+
+        for _ in 0..2 {
+            let due = clock.borrow_mut().take_due();
+            for callback in due {
+                callback();
+            }
+        }
+        assert_eq!(*runs.borrow(), 2);
+    }
+
+    #[test]
+    fn frame_history_is_capped_and_averaged() {
+        let mut frames = FrameTimes::new(3);
+        assert_eq!(frames.fps(), 0.0);
+        assert_eq!(frames.last(), None);
+
+        frames.frames = vec![ms(10), ms(20), ms(30)].into_iter().collect();
+        assert_eq!(frames.last(), Some(ms(30)));
+        assert_eq!(frames.average(), ms(20));
+        assert_eq!(frames.longest(), ms(30));
+        assert_eq!(frames.fps(), 50.0);
+
+        frames.frame();
+        assert_eq!(frames.frames.len(), 3);
+        assert_eq!(frames.frames[0], ms(20));
+    }
+
+    #[test]
+    fn partial_steps_become_alpha() {
+        let mut sim = FixedStep::new(100);
+        assert_eq!(sim.advance(ms(4)), 0);
+        assert!((sim.alpha() - 0.4).abs() < 1e-6);
+
+        assert_eq!(sim.advance(ms(25)), 2);
+        assert!((sim.alpha() - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn stalls_are_clamped() {
+        let mut sim = FixedStep::new(100);
+        assert_eq!(sim.advance(ms(5000)), (MAX_FRAME_MS / 10) as u32);
+        assert_eq!(sim.alpha(), 0.0);
+        // The dropped time is gone, not owed to the next frame.
+        assert_eq!(sim.advance(ms(5010)), 1);
     }
 
-    pub fn frame_time(&mut self) -> i64 {
-        let origin = self.last_frame;
-        self.last_frame = SteadyTime::now();
-        (self.last_frame - origin).num_milliseconds()
+    #[test]
+    fn time_going_backwards_adds_nothing() {
+        let mut sim = FixedStep::new(100);
+        sim.advance(ms(105));
+        assert_eq!(sim.advance(ms(50)), 0);
+        assert!((sim.alpha() - 0.5).abs() < 1e-6);
+        // Steps count again from the earlier time.
+        assert_eq!(sim.advance(ms(60)), 1);
     }
 }